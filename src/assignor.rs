@@ -0,0 +1,409 @@
+//! Partition assignment strategies for consumer groups.
+//!
+//! The group leader collects every member's [`Subscription`] and hands them
+//! to the [`PartitionAssignor`] all members agreed on, which decides which
+//! member consumes which partition. Implement the trait to plug in custom
+//! placement, e.g. colocating partitions with shard-local caches.
+//!
+//! Members may report the rack they run in, which [`Subscription::encode`]
+//! sends along in the subscription metadata, and assignors receive the racks
+//! hosting each partition's replicas, so partitions can be kept with members
+//! in the same rack to avoid cross-zone fetch traffic (KIP-881).
+
+use std::collections::{BTreeMap, BTreeSet};
+
+use crate::error::{KafkaError, Result};
+use crate::protocol::codec::{
+    Cursor, Encode, write_array_len, write_nullable_bytes, write_nullable_string, write_string,
+};
+use crate::topic::TopicPartition;
+
+/// Version of the consumer protocol subscription written by [`Subscription::encode`],
+/// the first carrying the member's rack
+pub const SUBSCRIPTION_VERSION: i16 = 3;
+
+/// What one group member asked to consume
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Subscription {
+    pub topics: Vec<String>,
+    /// Opaque data produced by the member's assignor
+    pub user_data: Vec<u8>,
+    /// Partitions the member owned before this rebalance
+    pub owned_partitions: Vec<TopicPartition>,
+    /// Rack the member runs in, from its `client.rack` setting
+    pub rack: Option<String>,
+}
+
+impl Subscription {
+    /// Encodes the subscription as the metadata of a JoinGroup protocol
+    ///
+    /// Uses the consumer protocol layout of [`SUBSCRIPTION_VERSION`]. The
+    /// generation is written as unknown, -1.
+    pub fn encode(&self) -> Vec<u8> {
+        let mut buf = Vec::new();
+        SUBSCRIPTION_VERSION.encode(&mut buf);
+        write_array_len(&mut buf, Some(self.topics.len()), false);
+        for topic in &self.topics {
+            write_string(&mut buf, topic);
+        }
+        write_nullable_bytes(&mut buf, Some(&self.user_data));
+
+        let mut owned: BTreeMap<&str, Vec<i32>> = BTreeMap::new();
+        for partition in &self.owned_partitions {
+            owned
+                .entry(&partition.topic)
+                .or_default()
+                .push(partition.partition);
+        }
+        write_array_len(&mut buf, Some(owned.len()), false);
+        for (topic, partitions) in owned {
+            write_string(&mut buf, topic);
+            write_array_len(&mut buf, Some(partitions.len()), false);
+            for partition in partitions {
+                partition.encode(&mut buf);
+            }
+        }
+
+        (-1i32).encode(&mut buf); // Generation id
+        write_nullable_string(&mut buf, self.rack.as_deref());
+        buf
+    }
+
+    /// Decodes subscription metadata of any consumer protocol version
+    ///
+    /// Fields added after [`SUBSCRIPTION_VERSION`] are ignored, as the Java
+    /// client does.
+    pub fn decode(metadata: &[u8]) -> Result<Self> {
+        let mut cursor = Cursor::new(metadata);
+        let version: i16 = cursor.read()?;
+        if version < 0 {
+            return Err(KafkaError::Serialization(format!(
+                "invalid subscription version {version}"
+            )));
+        }
+
+        let mut subscription = Self::default();
+        for _ in 0..cursor.read_array_len(false)?.unwrap_or(0) {
+            subscription.topics.push(cursor.read_string()?);
+        }
+        subscription.user_data = cursor
+            .read_nullable_bytes()?
+            .map(<[u8]>::to_vec)
+            .unwrap_or_default();
+        if version >= 1 {
+            for _ in 0..cursor.read_array_len(false)?.unwrap_or(0) {
+                let topic = cursor.read_string()?;
+                for _ in 0..cursor.read_array_len(false)?.unwrap_or(0) {
+                    let partition = cursor.read()?;
+                    subscription
+                        .owned_partitions
+                        .push(TopicPartition::new(topic.clone(), partition));
+                }
+            }
+        }
+        if version >= 2 {
+            cursor.read::<i32>()?; // Generation id
+        }
+        if version >= 3 {
+            subscription.rack = cursor.read_nullable_string()?;
+        }
+        Ok(subscription)
+    }
+}
+
+/// Partitions handed to each member, keyed by member id
+pub type Assignment = BTreeMap<String, Vec<TopicPartition>>;
+
+/// Racks hosting a replica of each partition; partitions without rack information are absent
+pub type PartitionRacks = BTreeMap<TopicPartition, BTreeSet<String>>;
+
+/// A strategy for spreading partitions over group members
+pub trait PartitionAssignor: Send + Sync {
+    /// Protocol name members announce when joining, e.g. `range`
+    fn name(&self) -> &str;
+
+    /// Data every member attaches to its subscription for the leader's assignor
+    fn subscription_user_data(&self, _topics: &[String]) -> Vec<u8> {
+        Vec::new()
+    }
+
+    /// Assigns partitions to members
+    ///
+    /// `partitions_per_topic` holds the partition count of every subscribed
+    /// topic, `racks` where their replicas live and `subscriptions` every
+    /// member's subscription keyed by member id. Every member must appear in
+    /// the result, if only with no partitions.
+    fn assign(
+        &self,
+        partitions_per_topic: &BTreeMap<String, i32>,
+        racks: &PartitionRacks,
+        subscriptions: &BTreeMap<String, Subscription>,
+    ) -> Assignment;
+}
+
+/// Gives each member a contiguous range of every topic's partitions
+///
+/// Per topic, members subscribed to it are sorted by id and the partitions
+/// split into equal ranges, the first members taking one extra partition
+/// when the count does not divide evenly.
+///
+/// When members report racks and replica racks are known, as many
+/// partitions as possible are first given to members with a replica in
+/// their own rack, up to each member's share; the remaining partitions are
+/// then filled in order. Shares stay the same, but a member's partitions
+/// are no longer necessarily contiguous.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct RangeAssignor;
+
+impl PartitionAssignor for RangeAssignor {
+    fn name(&self) -> &str {
+        "range"
+    }
+
+    fn assign(
+        &self,
+        partitions_per_topic: &BTreeMap<String, i32>,
+        racks: &PartitionRacks,
+        subscriptions: &BTreeMap<String, Subscription>,
+    ) -> Assignment {
+        let mut assignment: Assignment = subscriptions
+            .keys()
+            .map(|member| (member.clone(), Vec::new()))
+            .collect();
+
+        for (topic, &partitions) in partitions_per_topic {
+            let members: Vec<(&String, Option<&str>)> = subscriptions
+                .iter()
+                .filter(|(_, subscription)| subscription.topics.contains(topic))
+                .map(|(member, subscription)| (member, subscription.rack.as_deref()))
+                .collect();
+            if members.is_empty() {
+                continue;
+            }
+
+            let partitions = partitions.max(0);
+            let per_member = partitions as usize / members.len();
+            let with_extra = partitions as usize % members.len();
+            let quota = |index: usize| per_member + usize::from(index < with_extra);
+
+            // Same-rack partitions first, as long as the members' shares allow
+            let local: Vec<Vec<usize>> = (0..partitions)
+                .map(|partition| {
+                    let replicas = racks.get(&TopicPartition::new(topic, partition));
+                    members
+                        .iter()
+                        .enumerate()
+                        .filter(|(_, (_, rack))| {
+                            rack.zip(replicas)
+                                .is_some_and(|(rack, replicas)| replicas.contains(rack))
+                        })
+                        .map(|(index, _)| index)
+                        .collect()
+                })
+                .collect();
+            let quotas: Vec<usize> = (0..members.len()).map(quota).collect();
+            let mut assigned: Vec<Vec<i32>> = match_local(&local, &quotas)
+                .into_iter()
+                .map(|held| held.into_iter().map(|partition| partition as i32).collect())
+                .collect();
+            let mut unassigned: BTreeSet<i32> = (0..partitions).collect();
+            for partition in assigned.iter().flatten() {
+                unassigned.remove(partition);
+            }
+
+            for (index, owned) in assigned.iter_mut().enumerate() {
+                while owned.len() < quota(index) {
+                    let Some(partition) = unassigned.pop_first() else {
+                        break;
+                    };
+                    owned.push(partition);
+                }
+                owned.sort_unstable();
+            }
+
+            for ((member, _), owned) in members.into_iter().zip(assigned) {
+                let member_partitions = assignment
+                    .get_mut(member)
+                    .expect("every member is assigned");
+                member_partitions.extend(
+                    owned
+                        .into_iter()
+                        .map(|partition| TopicPartition::new(topic, partition)),
+                );
+            }
+        }
+        assignment
+    }
+}
+
+/// Gives as many partitions as possible to a member sharing a rack with one of their replicas
+///
+/// `local[p]` lists the members whose rack hosts a replica of partition `p`,
+/// and `quotas` caps how many partitions each member takes. A greedy pass in
+/// member order could let an early member take the only local partitions of
+/// a later one; following augmenting paths moves such partitions over, so
+/// the number of rack-local placements is maximal. Returns the partitions
+/// held by each member.
+fn match_local(local: &[Vec<usize>], quotas: &[usize]) -> Vec<Vec<usize>> {
+    let mut held = vec![Vec::new(); quotas.len()];
+    for partition in 0..local.len() {
+        let mut visited = vec![false; quotas.len()];
+        augment(partition, local, quotas, &mut held, &mut visited);
+    }
+    held
+}
+
+/// Places `partition` with a local member, moving that member's other partitions along if needed
+fn augment(
+    partition: usize,
+    local: &[Vec<usize>],
+    quotas: &[usize],
+    held: &mut [Vec<usize>],
+    visited: &mut [bool],
+) -> bool {
+    for &member in &local[partition] {
+        if std::mem::replace(&mut visited[member], true) {
+            continue;
+        }
+        if held[member].len() < quotas[member] {
+            held[member].push(partition);
+            return true;
+        }
+        for slot in 0..held[member].len() {
+            // `member` is visited, so moving its partition elsewhere leaves `held[member]` alone
+            if augment(held[member][slot], local, quotas, held, visited) {
+                held[member][slot] = partition;
+                return true;
+            }
+        }
+    }
+    false
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn member(rack: Option<&str>) -> Subscription {
+        Subscription {
+            topics: vec!["t".into()],
+            rack: rack.map(Into::into),
+            ..Subscription::default()
+        }
+    }
+
+    fn assign(
+        partitions: i32,
+        racks: &[(i32, &[&str])],
+        members: &[(&str, Option<&str>)],
+    ) -> BTreeMap<String, Vec<i32>> {
+        let partitions_per_topic = BTreeMap::from([("t".to_string(), partitions)]);
+        let racks = racks
+            .iter()
+            .map(|&(partition, racks)| {
+                let racks = racks.iter().map(|rack| rack.to_string()).collect();
+                (TopicPartition::new("t", partition), racks)
+            })
+            .collect();
+        let subscriptions = members
+            .iter()
+            .map(|&(id, rack)| (id.to_string(), member(rack)))
+            .collect();
+        RangeAssignor
+            .assign(&partitions_per_topic, &racks, &subscriptions)
+            .into_iter()
+            .map(|(member, partitions)| {
+                let partitions = partitions.iter().map(|p| p.partition).collect();
+                (member, partitions)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn even_split_is_contiguous() {
+        let assignment = assign(6, &[], &[("a", None), ("b", None), ("c", None)]);
+        assert_eq!(assignment["a"], [0, 1]);
+        assert_eq!(assignment["b"], [2, 3]);
+        assert_eq!(assignment["c"], [4, 5]);
+    }
+
+    #[test]
+    fn remainder_goes_to_the_first_members() {
+        let assignment = assign(8, &[], &[("c", None), ("a", None), ("b", None)]);
+        assert_eq!(assignment["a"], [0, 1, 2]);
+        assert_eq!(assignment["b"], [3, 4, 5]);
+        assert_eq!(assignment["c"], [6, 7]);
+
+        let assignment = assign(1, &[], &[("a", None), ("b", None)]);
+        assert_eq!(assignment["a"], [0]);
+        assert!(assignment["b"].is_empty());
+    }
+
+    #[test]
+    fn unsubscribed_members_get_nothing() {
+        let partitions_per_topic = BTreeMap::from([("t".to_string(), 2)]);
+        let subscriptions = BTreeMap::from([
+            ("a".to_string(), member(None)),
+            ("b".to_string(), Subscription::default()),
+        ]);
+        let assignment = RangeAssignor.assign(
+            &partitions_per_topic,
+            &PartitionRacks::new(),
+            &subscriptions,
+        );
+        assert_eq!(assignment["a"].len(), 2);
+        assert!(assignment["b"].is_empty());
+    }
+
+    #[test]
+    fn partitions_stay_in_their_rack() {
+        let racks: &[(i32, &[&str])] = &[(0, &["r2"]), (1, &["r2"]), (2, &["r1"]), (3, &["r1"])];
+        let assignment = assign(4, racks, &[("a", Some("r1")), ("b", Some("r2"))]);
+        assert_eq!(assignment["a"], [2, 3]);
+        assert_eq!(assignment["b"], [0, 1]);
+
+        // Shares win over racks
+        let racks: &[(i32, &[&str])] = &[(0, &["r1"]), (1, &["r1"]), (2, &["r1"]), (3, &["r1"])];
+        let assignment = assign(4, racks, &[("a", Some("r1")), ("b", Some("r2"))]);
+        assert_eq!(assignment["a"], [0, 1]);
+        assert_eq!(assignment["b"], [2, 3]);
+    }
+
+    #[test]
+    fn earlier_members_do_not_starve_later_ones() {
+        // Partition 0 is local to both members, partition 1 only to `a`
+        let racks: &[(i32, &[&str])] = &[(0, &["r1", "r2"]), (1, &["r1"])];
+        let assignment = assign(2, racks, &[("a", Some("r1")), ("b", Some("r2"))]);
+        assert_eq!(assignment["a"], [1]);
+        assert_eq!(assignment["b"], [0]);
+    }
+
+    #[test]
+    fn subscription_round_trips_with_rack() {
+        let subscription = Subscription {
+            topics: vec!["t".into(), "u".into()],
+            user_data: b"data".to_vec(),
+            owned_partitions: vec![
+                TopicPartition::new("t", 0),
+                TopicPartition::new("t", 2),
+                TopicPartition::new("u", 1),
+            ],
+            rack: Some("r1".into()),
+        };
+        let encoded = subscription.encode();
+        assert_eq!(&encoded[..2], &SUBSCRIPTION_VERSION.to_be_bytes());
+        assert!(encoded.ends_with(b"\x00\x02r1"));
+        assert_eq!(Subscription::decode(&encoded).unwrap(), subscription);
+    }
+
+    #[test]
+    fn v0_subscription_decodes() {
+        let mut metadata = 0i16.to_be_bytes().to_vec();
+        metadata.extend(1i32.to_be_bytes());
+        metadata.extend(1i16.to_be_bytes());
+        metadata.push(b't');
+        metadata.extend((-1i32).to_be_bytes()); // Null user data
+        assert_eq!(Subscription::decode(&metadata).unwrap(), member(None));
+        assert!(Subscription::decode(&metadata[..5]).is_err());
+    }
+}
@@ -0,0 +1,130 @@
+//! SASL authentication of broker connections.
+//!
+//! Authentication runs right after connecting: a SaslHandshake request
+//! picks the mechanism, then SaslAuthenticate requests carry the mechanism's
+//! tokens. Only PLAIN is implemented, which sends the password as is and so
+//! belongs on trusted networks or behind TLS.
+
+use std::fmt;
+use std::io::{Read, Write};
+
+use crate::connection::Connection;
+use crate::error::{KafkaError, Result};
+use crate::protocol::error_code::ErrorCode;
+use crate::protocol::{ApiKey, sasl};
+
+/// SASL mechanisms the client can authenticate with
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum SaslMechanism {
+    #[default]
+    Plain,
+}
+
+impl SaslMechanism {
+    /// Name of the mechanism as sent in the handshake
+    pub const fn name(self) -> &'static str {
+        match self {
+            Self::Plain => "PLAIN",
+        }
+    }
+
+    /// Parses a mechanism name, ignoring case
+    pub fn from_name(name: &str) -> Result<Self> {
+        if name.eq_ignore_ascii_case("plain") {
+            Ok(Self::Plain)
+        } else {
+            Err(KafkaError::Config(format!(
+                "SASL mechanism `{name}` is not supported"
+            )))
+        }
+    }
+}
+
+impl fmt::Display for SaslMechanism {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// Mechanism and credentials to authenticate with
+#[derive(Clone, Default, PartialEq, Eq)]
+pub struct SaslCredentials {
+    pub mechanism: SaslMechanism,
+    pub username: String,
+    pub password: String,
+}
+
+impl SaslCredentials {
+    /// PLAIN credentials
+    pub fn plain(username: impl Into<String>, password: impl Into<String>) -> Self {
+        Self {
+            mechanism: SaslMechanism::Plain,
+            username: username.into(),
+            password: password.into(),
+        }
+    }
+}
+
+impl fmt::Debug for SaslCredentials {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SaslCredentials")
+            .field("mechanism", &self.mechanism)
+            .field("username", &self.username)
+            .field("password", &"<redacted>")
+            .finish()
+    }
+}
+
+impl<S: Read + Write> Connection<S> {
+    /// Authenticates the connection; must run before any other request
+    ///
+    /// TCP connections remember the credentials and authenticate again
+    /// whenever they are reset.
+    pub fn authenticate(&mut self, credentials: &SaslCredentials) -> Result<()> {
+        let mechanism = credentials.mechanism.name();
+
+        let body = sasl::encode_handshake_request(mechanism);
+        let response = self.exchange(ApiKey::SaslHandshake, sasl::HANDSHAKE_VERSION, &body)?;
+        let handshake = sasl::decode_handshake_response(&response)?;
+        if let Some(code) = ErrorCode::from_i16(handshake.error_code) {
+            return Err(KafkaError::Authentication(format!(
+                "broker rejected mechanism {mechanism} ({code}), it offers {:?}",
+                handshake.mechanisms
+            )));
+        }
+
+        let token = match credentials.mechanism {
+            SaslMechanism::Plain => plain_token(credentials),
+        };
+        let body = sasl::encode_authenticate_request(&token);
+        let response =
+            self.exchange(ApiKey::SaslAuthenticate, sasl::AUTHENTICATE_VERSION, &body)?;
+        let authenticate = sasl::decode_authenticate_response(&response)?;
+        if let Some(code) = ErrorCode::from_i16(authenticate.error_code) {
+            return Err(KafkaError::Authentication(
+                authenticate
+                    .error_message
+                    .unwrap_or_else(|| format!("SASL {mechanism} failed: {code}")),
+            ));
+        }
+
+        self.set_authenticated(credentials.clone());
+        Ok(())
+    }
+
+    fn exchange(&mut self, api_key: ApiKey, version: i16, body: &[u8]) -> Result<Vec<u8>> {
+        let correlation_id = self.send_request(api_key.as_i16(), version, body)?;
+        self.receive_response(correlation_id)
+    }
+}
+
+/// PLAIN token: empty authorization id, username and password separated by NUL bytes
+fn plain_token(credentials: &SaslCredentials) -> Vec<u8> {
+    let mut token = Vec::with_capacity(2 + credentials.username.len() + credentials.password.len());
+    token.push(0);
+    token.extend_from_slice(credentials.username.as_bytes());
+    token.push(0);
+    token.extend_from_slice(credentials.password.as_bytes());
+    token
+}
@@ -0,0 +1,336 @@
+//! Retry timing shared by every layer that retries.
+//!
+//! A [`BackoffPolicy`] describes how retries are spaced and when to stop:
+//! delays grow with decorrelated jitter between an initial and a maximum
+//! delay, bounded by a retry count and a total elapsed time. A policy may
+//! also draw from a shared [`RetryBudget`], which caps retries to a fraction
+//! of successful requests so a struggling cluster is not hammered by every
+//! client retrying at once.
+
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// How retries are spaced and when they stop
+#[derive(Debug, Clone)]
+pub struct BackoffPolicy {
+    /// Delay before the first retry
+    pub initial: Duration,
+    /// Upper bound for any single delay
+    pub max: Duration,
+    /// Stop retrying once this much time passed since the first attempt
+    pub max_elapsed: Option<Duration>,
+    /// Stop retrying after this many retries
+    pub max_retries: Option<u32>,
+    /// Budget shared by every operation retrying under this policy
+    pub budget: Option<Arc<RetryBudget>>,
+}
+
+impl Default for BackoffPolicy {
+    fn default() -> Self {
+        Self {
+            initial: Duration::from_millis(100),
+            max: Duration::from_secs(1),
+            max_elapsed: None,
+            max_retries: Some(3),
+            budget: None,
+        }
+    }
+}
+
+impl PartialEq for BackoffPolicy {
+    fn eq(&self, other: &Self) -> bool {
+        let same_budget = match (&self.budget, &other.budget) {
+            (Some(a), Some(b)) => Arc::ptr_eq(a, b),
+            (None, None) => true,
+            _ => false,
+        };
+        self.initial == other.initial
+            && self.max == other.max
+            && self.max_elapsed == other.max_elapsed
+            && self.max_retries == other.max_retries
+            && same_budget
+    }
+}
+
+impl Eq for BackoffPolicy {}
+
+impl BackoffPolicy {
+    /// A policy that never retries
+    pub const fn never() -> Self {
+        Self {
+            initial: Duration::ZERO,
+            max: Duration::ZERO,
+            max_elapsed: None,
+            max_retries: Some(0),
+            budget: None,
+        }
+    }
+
+    /// Starts tracking the retries of one operation
+    pub fn start(&self) -> Backoff {
+        self.start_seeded(RandomState::new().build_hasher().finish())
+    }
+
+    /// Like [`start`](Self::start), with the jitter drawn from `seed`
+    fn start_seeded(&self, seed: u64) -> Backoff {
+        Backoff {
+            policy: self.clone(),
+            started: Instant::now(),
+            retries: 0,
+            previous: self.initial,
+            rng: seed | 1,
+        }
+    }
+
+    /// Runs `operation` until it succeeds, `is_retriable` rejects its error or retries run out
+    pub fn retry<T, E>(
+        &self,
+        mut operation: impl FnMut() -> Result<T, E>,
+        is_retriable: impl Fn(&E) -> bool,
+    ) -> Result<T, E> {
+        let mut backoff = self.start();
+        loop {
+            match operation() {
+                Ok(value) => {
+                    if let Some(budget) = &self.budget {
+                        budget.record_success();
+                    }
+                    return Ok(value);
+                }
+                Err(e) if is_retriable(&e) => match backoff.next_delay() {
+                    Some(delay) => thread::sleep(delay),
+                    None => return Err(e),
+                },
+                Err(e) => return Err(e),
+            }
+        }
+    }
+}
+
+/// Retry state of one operation
+#[derive(Debug)]
+pub struct Backoff {
+    policy: BackoffPolicy,
+    started: Instant,
+    retries: u32,
+    previous: Duration,
+    /// xorshift state for jitter
+    rng: u64,
+}
+
+impl Backoff {
+    /// Delay to wait before the next retry, or `None` once retrying should stop
+    ///
+    /// Uses decorrelated jitter: a random delay between the initial delay
+    /// and three times the previous one, capped at the maximum.
+    pub fn next_delay(&mut self) -> Option<Duration> {
+        if self
+            .policy
+            .max_retries
+            .is_some_and(|max| self.retries >= max)
+        {
+            return None;
+        }
+
+        let upper = self.previous.saturating_mul(3).min(self.policy.max);
+        let lower = self.policy.initial.min(upper);
+        let delay = lower + (upper - lower).mul_f64(self.next_random());
+
+        if let Some(max_elapsed) = self.policy.max_elapsed
+            && self.started.elapsed() + delay > max_elapsed
+        {
+            return None;
+        }
+        if let Some(budget) = &self.policy.budget
+            && !budget.try_withdraw()
+        {
+            return None;
+        }
+
+        self.retries += 1;
+        self.previous = delay;
+        Some(delay)
+    }
+
+    /// Number of retries handed out so far
+    pub const fn retries(&self) -> u32 {
+        self.retries
+    }
+
+    /// Restarts the schedule, e.g. after the operation made progress
+    pub fn reset(&mut self) {
+        self.started = Instant::now();
+        self.retries = 0;
+        self.previous = self.policy.initial;
+    }
+
+    /// Uniform random number in `[0, 1)`
+    fn next_random(&mut self) -> f64 {
+        self.rng ^= self.rng << 13;
+        self.rng ^= self.rng >> 7;
+        self.rng ^= self.rng << 17;
+        (self.rng >> 11) as f64 / (1u64 << 53) as f64
+    }
+}
+
+impl Iterator for Backoff {
+    type Item = Duration;
+
+    fn next(&mut self) -> Option<Duration> {
+        self.next_delay()
+    }
+}
+
+/// Token bucket allowing retries only in proportion to successful requests
+#[derive(Debug)]
+pub struct RetryBudget {
+    tokens: Mutex<f64>,
+    max_tokens: f64,
+    /// Tokens deposited by every success, i.e. retries allowed per success
+    ratio: f64,
+}
+
+impl RetryBudget {
+    /// Allows `ratio` retries per successful request, saving up at most `max_tokens`
+    pub fn new(ratio: f64, max_tokens: u32) -> Self {
+        let max_tokens = f64::from(max_tokens);
+        Self {
+            tokens: Mutex::new(max_tokens),
+            max_tokens,
+            ratio,
+        }
+    }
+
+    /// Credits the budget for a successful request
+    pub fn record_success(&self) {
+        let mut tokens = self.lock();
+        *tokens = (*tokens + self.ratio).min(self.max_tokens);
+    }
+
+    /// Takes one retry from the budget, if any is left
+    pub fn try_withdraw(&self) -> bool {
+        let mut tokens = self.lock();
+        if *tokens >= 1.0 {
+            *tokens -= 1.0;
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Retries currently available
+    pub fn available(&self) -> u32 {
+        *self.lock() as u32
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, f64> {
+        self.tokens
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn policy(initial_ms: u64, max_ms: u64) -> BackoffPolicy {
+        BackoffPolicy {
+            initial: Duration::from_millis(initial_ms),
+            max: Duration::from_millis(max_ms),
+            max_elapsed: None,
+            max_retries: None,
+            budget: None,
+        }
+    }
+
+    #[test]
+    fn delays_stay_within_decorrelated_jitter_bounds() {
+        let policy = policy(100, 60_000);
+        for seed in 0..20 {
+            let mut backoff = policy.start_seeded(seed);
+            let mut previous = policy.initial;
+            for _ in 0..30 {
+                let delay = backoff.next_delay().unwrap();
+                assert!(delay >= policy.initial, "{delay:?} below the initial delay");
+                assert!(delay <= previous * 3, "{delay:?} above 3 x {previous:?}");
+                previous = delay;
+            }
+        }
+    }
+
+    #[test]
+    fn delays_are_capped() {
+        let policy = policy(100, 250);
+        let mut backoff = policy.start_seeded(7);
+        let delays: Vec<Duration> = (&mut backoff).take(100).collect();
+        assert_eq!(delays.len(), 100);
+        assert!(delays.iter().all(|delay| *delay <= policy.max));
+        // Growth reaches the cap rather than stalling at the initial delay
+        assert!(
+            delays
+                .iter()
+                .any(|delay| *delay > Duration::from_millis(200))
+        );
+    }
+
+    #[test]
+    fn same_seed_gives_same_schedule() {
+        let policy = policy(10, 10_000);
+        let first: Vec<_> = policy.start_seeded(100).take(10).collect();
+        let second: Vec<_> = policy.start_seeded(100).take(10).collect();
+        assert_eq!(first, second);
+        assert_ne!(first, policy.start_seeded(200).take(10).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn retries_stop_at_the_limit_until_reset() {
+        let policy = BackoffPolicy {
+            max_retries: Some(3),
+            ..policy(1, 10)
+        };
+        let mut backoff = policy.start_seeded(1);
+        assert_eq!((&mut backoff).count(), 3);
+        assert_eq!(backoff.retries(), 3);
+        backoff.reset();
+        assert!(backoff.next_delay().is_some());
+
+        let elapsed = BackoffPolicy {
+            max_elapsed: Some(Duration::ZERO),
+            ..policy
+        };
+        assert_eq!(elapsed.start_seeded(1).next_delay(), None);
+    }
+
+    #[test]
+    fn budget_runs_out_and_refills_on_success() {
+        let budget = Arc::new(RetryBudget::new(0.5, 2));
+        let policy = BackoffPolicy {
+            budget: Some(Arc::clone(&budget)),
+            ..policy(1, 1)
+        };
+
+        assert_eq!(policy.start_seeded(1).count(), 2);
+        assert_eq!(budget.available(), 0);
+
+        let mut attempts = 0;
+        let result: Result<(), ()> = policy.retry(
+            || {
+                attempts += 1;
+                Err(())
+            },
+            |_| true,
+        );
+        assert!(result.is_err());
+        assert_eq!(attempts, 1);
+
+        for _ in 0..6 {
+            policy.retry(|| Ok::<_, ()>(()), |_| true).unwrap();
+        }
+        // Successes refill the budget only up to its maximum
+        assert_eq!(budget.available(), 2);
+    }
+}
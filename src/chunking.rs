@@ -0,0 +1,297 @@
+//! Splitting of oversized payloads into chunked records and their reassembly.
+//!
+//! Payloads larger than the configured chunk size are cut into consecutive
+//! chunks that share the original key and carry three headers: a payload id,
+//! the chunk index and the chunk count. The user headers travel on the first
+//! chunk. Payloads that fit in one chunk are passed through untouched.
+//!
+//! Reassembly needs every chunk on one partition. The shared key ensures
+//! that under key-based partitioning; chunks of a payload without a key must
+//! be produced to an explicit partition.
+
+use std::collections::{HashMap, VecDeque};
+use std::process;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::error::{KafkaError, Result};
+
+/// Header carrying the id shared by all chunks of one payload
+pub const CHUNK_ID_HEADER: &str = "kafka.chunk.id";
+
+/// Header carrying the zero-based position of a chunk, as a big-endian `u32`
+pub const CHUNK_INDEX_HEADER: &str = "kafka.chunk.index";
+
+/// Header carrying the number of chunks of the payload, as a big-endian `u32`
+pub const CHUNK_COUNT_HEADER: &str = "kafka.chunk.count";
+
+/// A record as seen by the chunking layer
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct Chunk {
+    pub key: Option<Vec<u8>>,
+    pub value: Vec<u8>,
+    pub headers: Vec<(String, Vec<u8>)>,
+}
+
+impl Chunk {
+    fn header(&self, name: &str) -> Option<&[u8]> {
+        self.headers
+            .iter()
+            .find(|(key, _)| key == name)
+            .map(|(_, value)| value.as_slice())
+    }
+}
+
+/// Splits payloads larger than a fixed size into chunks
+#[derive(Debug)]
+pub struct Chunker {
+    max_chunk_size: usize,
+    /// Prefix making payload ids unique across processes
+    id_prefix: String,
+    next_id: u64,
+}
+
+impl Chunker {
+    /// Creates a chunker emitting values of at most `max_chunk_size` bytes
+    pub fn new(max_chunk_size: usize) -> Self {
+        let started = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |elapsed| elapsed.as_nanos());
+
+        Self {
+            max_chunk_size: max_chunk_size.max(1),
+            id_prefix: format!("{:x}-{started:x}", process::id()),
+            next_id: 0,
+        }
+    }
+
+    /// Splits a payload into the records to produce, in order
+    pub fn split(
+        &mut self,
+        key: Option<&[u8]>,
+        value: &[u8],
+        headers: &[(String, Vec<u8>)],
+    ) -> Vec<Chunk> {
+        if value.len() <= self.max_chunk_size {
+            return vec![Chunk {
+                key: key.map(<[u8]>::to_vec),
+                value: value.to_vec(),
+                headers: headers.to_vec(),
+            }];
+        }
+
+        let id = format!("{}-{:x}", self.id_prefix, self.next_id);
+        self.next_id += 1;
+
+        let count = value.len().div_ceil(self.max_chunk_size) as u32;
+        value
+            .chunks(self.max_chunk_size)
+            .zip(0u32..)
+            .map(|(part, index)| {
+                let mut chunk_headers = if index == 0 {
+                    headers.to_vec()
+                } else {
+                    Vec::new()
+                };
+                chunk_headers.push((CHUNK_ID_HEADER.to_string(), id.clone().into_bytes()));
+                chunk_headers.push((CHUNK_INDEX_HEADER.to_string(), index.to_be_bytes().to_vec()));
+                chunk_headers.push((CHUNK_COUNT_HEADER.to_string(), count.to_be_bytes().to_vec()));
+
+                Chunk {
+                    key: key.map(<[u8]>::to_vec),
+                    value: part.to_vec(),
+                    headers: chunk_headers,
+                }
+            })
+            .collect()
+    }
+}
+
+/// Chunks received so far for one payload
+#[derive(Debug)]
+struct PartialPayload {
+    key: Option<Vec<u8>>,
+    headers: Vec<(String, Vec<u8>)>,
+    parts: Vec<Option<Vec<u8>>>,
+    received: usize,
+    bytes: usize,
+}
+
+/// Reassembles payloads from consumed chunks
+#[derive(Debug)]
+pub struct Reassembler {
+    pending: HashMap<String, PartialPayload>,
+    /// Payload ids in the order their first chunk arrived
+    arrival: VecDeque<String>,
+    pending_bytes: usize,
+    max_pending_bytes: usize,
+    evicted: u64,
+}
+
+impl Reassembler {
+    /// Creates a reassembler buffering at most `max_pending_bytes` of incomplete payloads
+    pub fn new(max_pending_bytes: usize) -> Self {
+        Self {
+            pending: HashMap::new(),
+            arrival: VecDeque::new(),
+            pending_bytes: 0,
+            max_pending_bytes,
+            evicted: 0,
+        }
+    }
+
+    /// Feeds one consumed record, returning the payload once it is complete
+    ///
+    /// Records without chunk headers are returned as they are. When the
+    /// buffered bytes exceed the limit, the oldest incomplete payloads are
+    /// dropped (see [`Reassembler::evicted`]). A chunk whose count could
+    /// never fit within the limit is rejected with an error.
+    pub fn push(&mut self, chunk: Chunk) -> Result<Option<Chunk>> {
+        let Some(id) = chunk.header(CHUNK_ID_HEADER) else {
+            return Ok(Some(chunk));
+        };
+        let id = String::from_utf8_lossy(id).into_owned();
+        let index = read_u32_header(&chunk, CHUNK_INDEX_HEADER)? as usize;
+        let count = read_u32_header(&chunk, CHUNK_COUNT_HEADER)? as usize;
+        // The count comes from the wire: bound it before allocating a slot per chunk
+        let max_count = self.max_pending_bytes / chunk.value.len().max(1);
+        if count == 0 || count > max_count {
+            return Err(KafkaError::Serialization(format!(
+                "payload {id} claims {count} chunks, more than fit in {} pending bytes",
+                self.max_pending_bytes
+            )));
+        }
+        if index >= count {
+            return Err(KafkaError::Serialization(format!(
+                "chunk {index} of payload {id} is beyond its count {count}"
+            )));
+        }
+
+        if !self.pending.contains_key(&id) {
+            self.arrival.push_back(id.clone());
+        }
+        let partial = self
+            .pending
+            .entry(id.clone())
+            .or_insert_with(|| PartialPayload {
+                key: chunk.key.clone(),
+                headers: Vec::new(),
+                parts: vec![None; count],
+                received: 0,
+                bytes: 0,
+            });
+        if partial.parts.len() != count {
+            return Err(KafkaError::Serialization(format!(
+                "chunks of payload {id} disagree on the chunk count"
+            )));
+        }
+
+        // Redelivered chunks (e.g. after a rebalance) are ignored
+        if partial.parts[index].is_none() {
+            if index == 0 {
+                partial.headers = chunk
+                    .headers
+                    .into_iter()
+                    .filter(|(key, _)| !is_chunk_header(key))
+                    .collect();
+            }
+            partial.received += 1;
+            partial.bytes += chunk.value.len();
+            self.pending_bytes += chunk.value.len();
+            partial.parts[index] = Some(chunk.value);
+        }
+
+        if partial.received == count {
+            let partial = self.remove(&id).expect("payload is pending");
+            return Ok(Some(Chunk {
+                key: partial.key,
+                value: partial.parts.into_iter().flatten().flatten().collect(),
+                headers: partial.headers,
+            }));
+        }
+
+        while self.pending_bytes > self.max_pending_bytes {
+            let Some(oldest) = self.arrival.front().cloned() else {
+                break;
+            };
+            self.remove(&oldest);
+            self.evicted += 1;
+        }
+        Ok(None)
+    }
+
+    /// Number of payloads still waiting for chunks
+    pub fn pending(&self) -> usize {
+        self.pending.len()
+    }
+
+    /// Number of incomplete payloads dropped to stay within the memory limit
+    pub const fn evicted(&self) -> u64 {
+        self.evicted
+    }
+
+    fn remove(&mut self, id: &str) -> Option<PartialPayload> {
+        let partial = self.pending.remove(id)?;
+        self.arrival.retain(|pending| pending != id);
+        self.pending_bytes -= partial.bytes;
+        Some(partial)
+    }
+}
+
+fn is_chunk_header(name: &str) -> bool {
+    matches!(
+        name,
+        CHUNK_ID_HEADER | CHUNK_INDEX_HEADER | CHUNK_COUNT_HEADER
+    )
+}
+
+fn read_u32_header(chunk: &Chunk, name: &str) -> Result<u32> {
+    chunk
+        .header(name)
+        .and_then(|value| value.try_into().ok())
+        .map(u32::from_be_bytes)
+        .ok_or_else(|| KafkaError::Serialization(format!("missing or malformed {name} header")))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn chunk(index: u32, count: u32, value: &[u8]) -> Chunk {
+        Chunk {
+            key: None,
+            value: value.to_vec(),
+            headers: vec![
+                (CHUNK_ID_HEADER.to_string(), b"id".to_vec()),
+                (CHUNK_INDEX_HEADER.to_string(), index.to_be_bytes().to_vec()),
+                (CHUNK_COUNT_HEADER.to_string(), count.to_be_bytes().to_vec()),
+            ],
+        }
+    }
+
+    #[test]
+    fn split_and_reassemble() {
+        let value: Vec<u8> = (0..=255).collect();
+        let headers = [("trace".to_string(), b"1".to_vec())];
+        let chunks = Chunker::new(100).split(Some(b"key"), &value, &headers);
+        assert_eq!(chunks.len(), 3);
+
+        let mut reassembler = Reassembler::new(1024);
+        assert_eq!(reassembler.push(chunks[2].clone()).unwrap(), None);
+        assert_eq!(reassembler.push(chunks[0].clone()).unwrap(), None);
+        let payload = reassembler.push(chunks[1].clone()).unwrap().unwrap();
+        assert_eq!(payload.key.as_deref(), Some(&b"key"[..]));
+        assert_eq!(payload.value, value);
+        assert_eq!(payload.headers, headers);
+        assert_eq!(reassembler.pending(), 0);
+    }
+
+    #[test]
+    fn implausible_chunk_counts_are_rejected() {
+        let mut reassembler = Reassembler::new(1024);
+        assert!(reassembler.push(chunk(0, 0, b"x")).is_err());
+        assert!(reassembler.push(chunk(0, u32::MAX, &[0; 16])).is_err());
+        assert!(reassembler.push(chunk(0, 65, &[0; 16])).is_err());
+        assert_eq!(reassembler.push(chunk(0, 64, &[0; 16])).unwrap(), None);
+        assert_eq!(reassembler.pending(), 1);
+    }
+}
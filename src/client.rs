@@ -0,0 +1,798 @@
+//! High-level client bundling a broker connection with what is known about the broker.
+
+use std::collections::{BTreeMap, HashMap};
+use std::io::{Read, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use crate::backoff::BackoffPolicy;
+use crate::config::ClientConfig;
+use crate::connection::{Connection, ConnectionDump};
+use crate::error::{ErrorKind, KafkaError, Result, TimeoutKind};
+use crate::events::{ClientEvents, NoopEvents};
+use crate::protocol::ApiKey;
+use crate::protocol::api_versions::{self, ClusterFeatures, VersionRange};
+use crate::protocol::codec::Cursor;
+use crate::protocol::error_code::ErrorCode;
+use crate::protocol::fetch::{self, FetchPosition, FetchRequest, FetchResponse};
+use crate::protocol::find_coordinator::{self, Coordinator, CoordinatorType};
+use crate::protocol::metadata::{self, MetadataResponse, TopicMetadata};
+use crate::protocol::produce::{self, Acks, ProduceRequest, ProduceResponse};
+use crate::records::{BatchCache, Record, RecordBatch};
+use crate::topic::TopicPartition;
+
+/// Snapshot of a client's state for diagnosing stuck pipelines
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct ClientDump {
+    pub connection: ConnectionDump,
+    /// Version ranges negotiated through ApiVersions, keyed by API key
+    pub api_versions: BTreeMap<i16, VersionRange>,
+    pub features: ClusterFeatures,
+}
+
+/// Metadata the client fetched last, for consulting without a round trip
+#[derive(Debug, Clone)]
+pub struct CachedMetadata {
+    /// Every fetch merged together; topics not fetched recently may be stale
+    pub metadata: MetadataResponse,
+    /// When the latest fetch completed
+    pub fetched_at: Instant,
+}
+
+impl CachedMetadata {
+    /// Time since the latest fetch completed
+    pub fn age(&self) -> Duration {
+        self.fetched_at.elapsed()
+    }
+}
+
+/// A client connected to a single broker
+pub struct KafkaClient<S = TcpStream> {
+    connection: Connection<S>,
+    /// Versions the broker supports, keyed by API key
+    api_versions: BTreeMap<i16, VersionRange>,
+    features: ClusterFeatures,
+    /// Coordinators found per group or transactional id
+    coordinators: HashMap<(CoordinatorType, String), Coordinator>,
+    metadata: Option<CachedMetadata>,
+    /// Decoded batches kept for [`fetch_records`](Self::fetch_records), if enabled
+    batch_cache: Option<BatchCache>,
+    events: Arc<dyn ClientEvents>,
+}
+
+impl KafkaClient<TcpStream> {
+    /// Connects to a broker and learns which API versions it supports
+    pub fn connect(addr: impl ToSocketAddrs, client_id: &str) -> Result<Self> {
+        Self::connect_with_events(addr, client_id, Arc::new(NoopEvents))
+    }
+
+    /// Connects to a broker, reporting lifecycle events to `events`
+    pub fn connect_with_events(
+        addr: impl ToSocketAddrs,
+        client_id: &str,
+        events: Arc<dyn ClientEvents>,
+    ) -> Result<Self> {
+        let connection = Connection::connect(addr, client_id)?;
+        if let Some(peer) = connection.peer() {
+            events.connection_established(peer);
+        }
+        Self::from_connection_with_events(connection, events)
+    }
+
+    /// Connects to the first reachable bootstrap server
+    ///
+    /// Each server's resolved addresses are tried with `connect.timeout.ms`,
+    /// so an unreachable broker fails over to the next one quickly instead
+    /// of waiting for the operating system's connect timeout.
+    pub fn from_config(config: &ClientConfig) -> Result<Self> {
+        Self::from_config_with_events(config, Arc::new(NoopEvents))
+    }
+
+    /// Connects to the first reachable bootstrap server, reporting events to `events`
+    pub fn from_config_with_events(
+        config: &ClientConfig,
+        events: Arc<dyn ClientEvents>,
+    ) -> Result<Self> {
+        let connection = config
+            .retry_backoff
+            .retry(|| Self::connect_bootstrap(config), is_unreachable)?;
+        if let Some(peer) = connection.peer() {
+            events.connection_established(peer);
+        }
+        Self::from_connection_with_events(connection, events)
+    }
+
+    /// Connects to the first bootstrap server that accepts a connection
+    fn connect_bootstrap(config: &ClientConfig) -> Result<Connection> {
+        let mut last_error = None;
+        for server in &config.bootstrap_servers {
+            match Connection::connect_with(
+                server.as_str(),
+                &config.client_id,
+                config.socket.clone(),
+            ) {
+                Ok(mut connection) => {
+                    if let Some(credentials) = &config.sasl {
+                        connection.authenticate(credentials)?;
+                    }
+                    return Ok(connection);
+                }
+                Err(e) => last_error = Some(e),
+            }
+        }
+
+        Err(last_error
+            .unwrap_or_else(|| KafkaError::Config("no bootstrap servers configured".into())))
+    }
+}
+
+impl<S: Read + Write> KafkaClient<S> {
+    /// Bootstraps a client on an already established connection
+    pub fn from_connection(connection: Connection<S>) -> Result<Self> {
+        Self::from_connection_with_events(connection, Arc::new(NoopEvents))
+    }
+
+    /// Bootstraps a client on an established connection, reporting events to `events`
+    pub fn from_connection_with_events(
+        connection: Connection<S>,
+        events: Arc<dyn ClientEvents>,
+    ) -> Result<Self> {
+        let mut client = Self {
+            connection,
+            api_versions: BTreeMap::new(),
+            features: ClusterFeatures::default(),
+            coordinators: HashMap::new(),
+            metadata: None,
+            batch_cache: None,
+            events,
+        };
+        client.send_api_versions_request()?;
+        Ok(client)
+    }
+
+    /// Refreshes the cache of API versions supported by the broker
+    ///
+    /// Starts with the newest ApiVersions version the client implements. An
+    /// older broker rejects it with UNSUPPORTED_VERSION but still lists its
+    /// own ApiVersions range, so the request is retried at that version
+    /// (down to v0, which every broker understands). INVALID_REQUEST to v3
+    /// means the client software fields were refused, and v2 is tried instead.
+    pub fn send_api_versions_request(&mut self) -> Result<&BTreeMap<i16, VersionRange>> {
+        let api_key = ApiKey::ApiVersions.as_i16();
+        let mut version = api_versions::VERSIONS.max;
+
+        loop {
+            let body = api_versions::encode_request(version);
+            let response = self.round_trip(api_key, version, &body)?;
+            let response = api_versions::decode_response(version, &response)?;
+            self.report_throttle(ApiKey::ApiVersions, response.throttle_time_ms);
+
+            match ErrorCode::from_i16(response.error_code) {
+                None => {
+                    self.api_versions = response.api_keys;
+                    self.features = response.features;
+                    return Ok(&self.api_versions);
+                }
+                Some(ErrorCode::UnsupportedVersion) => {
+                    let supported = response.api_keys.get(&api_key).map_or(0, |range| range.max);
+                    if supported >= version {
+                        return Err(KafkaError::UnsupportedVersion { api_key, version });
+                    }
+                    version = supported;
+                }
+                // v3 brokers validate the client software fields; v2 has none to reject
+                Some(ErrorCode::InvalidRequest) if version >= 3 => version = 2,
+                Some(code) => return Err(self.fatal(KafkaError::Broker(code))),
+            }
+        }
+    }
+
+    /// Versions the broker supports, keyed by API key
+    pub const fn api_versions(&self) -> &BTreeMap<i16, VersionRange> {
+        &self.api_versions
+    }
+
+    /// Captures connection counters, in-flight requests and negotiated versions
+    pub fn debug_dump(&self) -> ClientDump {
+        ClientDump {
+            connection: self.connection.debug_dump(),
+            api_versions: self.api_versions.clone(),
+            features: self.features.clone(),
+        }
+    }
+
+    /// Whether the broker accepts `version` of `api_key`
+    pub fn supports(&self, api_key: ApiKey, version: i16) -> bool {
+        self.api_versions
+            .get(&api_key.as_i16())
+            .is_some_and(|range| range.contains(version))
+    }
+
+    /// Supported and finalized feature flags, empty for brokers older than ApiVersions v3
+    pub const fn cluster_features(&self) -> &ClusterFeatures {
+        &self.features
+    }
+
+    /// Picks the highest version of `api_key` within both the broker's range and `client`
+    ///
+    /// `client` is the range of versions the caller can encode and decode;
+    /// its lower bound lets a request insist on fields older versions lack.
+    pub fn negotiate_version(&self, api_key: ApiKey, client: VersionRange) -> Result<i16> {
+        self.api_versions
+            .get(&api_key.as_i16())
+            .and_then(|range| range.intersect(&client))
+            .map(|range| range.max)
+            .ok_or(KafkaError::UnsupportedVersion {
+                api_key: api_key.as_i16(),
+                version: client.max,
+            })
+    }
+
+    /// Picks the version to retry with after the broker rejected `rejected`
+    pub fn downgrade_version(&self, api_key: ApiKey, rejected: i16) -> Option<i16> {
+        let range = self.api_versions.get(&api_key.as_i16())?;
+        let version = range.max.min(rejected - 1);
+        (version >= range.min).then_some(version)
+    }
+
+    /// Sends a request at the highest negotiated version, downgrading on UNSUPPORTED_VERSION
+    ///
+    /// `client` bounds the versions `encode` and `decode` handle. `encode`
+    /// builds the request body for a version and `decode` parses the response
+    /// body. Decoders report the broker's UNSUPPORTED_VERSION error, wherever
+    /// it appears in the response, as [`KafkaError::UnsupportedVersion`];
+    /// that error or `Broker(UnsupportedVersion)` triggers a retry at the next
+    /// lower version the broker advertises, never going below `client.min`.
+    pub fn send_versioned<T>(
+        &mut self,
+        api_key: ApiKey,
+        client: VersionRange,
+        encode: impl Fn(i16) -> Vec<u8>,
+        decode: impl Fn(i16, &[u8]) -> Result<T>,
+    ) -> Result<T> {
+        let mut version = self.negotiate_version(api_key, client)?;
+
+        loop {
+            let response = self.round_trip(api_key.as_i16(), version, &encode(version))?;
+
+            match decode(version, &response) {
+                Err(
+                    KafkaError::UnsupportedVersion { .. }
+                    | KafkaError::Broker(ErrorCode::UnsupportedVersion),
+                ) => {
+                    version = self
+                        .downgrade_version(api_key, version)
+                        .filter(|&lower| lower >= client.min)
+                        .ok_or(KafkaError::UnsupportedVersion {
+                            api_key: api_key.as_i16(),
+                            version,
+                        })?;
+                }
+                other => return other.map_err(|error| self.fatal(error)),
+            }
+        }
+    }
+
+    /// Fetches brokers, controller and partition leadership for `topics`
+    ///
+    /// An empty list fetches every topic in the cluster. Per-topic and
+    /// per-partition errors are left in the response for the caller to inspect.
+    /// The response also updates [`cached_metadata`](Self::cached_metadata),
+    /// which is then passed to [`ClientEvents::metadata_updated`]; fetching
+    /// every topic drops the cached ones that no longer exist.
+    pub fn fetch_metadata(&mut self, topics: &[&str]) -> Result<MetadataResponse> {
+        let all_topics = topics.is_empty();
+        let topics = (!all_topics).then_some(topics);
+        let response = self.send_versioned(
+            ApiKey::Metadata,
+            metadata::VERSIONS,
+            |version| metadata::encode_request(version, topics),
+            metadata::decode_response,
+        )?;
+        self.report_throttle(ApiKey::Metadata, response.throttle_time_ms);
+
+        let fetched_at = Instant::now();
+        let cached = match &mut self.metadata {
+            Some(cached) if !all_topics => {
+                cached.metadata.merge(response.clone());
+                cached.fetched_at = fetched_at;
+                cached
+            }
+            cached => cached.insert(CachedMetadata {
+                metadata: response.clone(),
+                fetched_at,
+            }),
+        };
+        self.events.metadata_updated(&cached.metadata);
+        Ok(response)
+    }
+
+    /// Waits until every partition of `topic` has a leader, polling metadata
+    ///
+    /// Meant for right after a topic was created, when producing to it would
+    /// otherwise race its leader elections. Fails with
+    /// [`KafkaError::NoLeaderAvailable`] if partitions are still leaderless
+    /// after `timeout`.
+    pub fn wait_for_leaders(&mut self, topic: &str, timeout: Duration) -> Result<TopicMetadata> {
+        let policy = BackoffPolicy {
+            initial: Duration::from_millis(50),
+            max: Duration::from_millis(500),
+            max_elapsed: Some(timeout),
+            max_retries: None,
+            budget: None,
+        };
+
+        policy.retry(
+            || {
+                let mut response = self.fetch_metadata(&[topic])?;
+                let position = response
+                    .topics
+                    .iter()
+                    .position(|known| known.name.as_deref() == Some(topic));
+                let Some(metadata) = position.map(|index| response.topics.swap_remove(index))
+                else {
+                    return Err(KafkaError::NoLeaderAvailable {
+                        topic: topic.to_string(),
+                        partitions: Vec::new(),
+                    });
+                };
+
+                match ErrorCode::from_i16(metadata.error_code) {
+                    Some(code) if !code.is_retriable() => {
+                        return Err(self.fatal(KafkaError::Broker(code)));
+                    }
+                    _ => {}
+                }
+                let leaderless: Vec<i32> = metadata
+                    .partitions
+                    .iter()
+                    .filter(|partition| partition.leader_id < 0)
+                    .map(|partition| partition.partition_index)
+                    .collect();
+                if metadata.error_code != 0
+                    || metadata.partitions.is_empty()
+                    || !leaderless.is_empty()
+                {
+                    return Err(KafkaError::NoLeaderAvailable {
+                        topic: topic.to_string(),
+                        partitions: leaderless,
+                    });
+                }
+                Ok(metadata)
+            },
+            |error| matches!(error, KafkaError::NoLeaderAvailable { .. }),
+        )
+    }
+
+    /// Metadata from earlier fetches, without contacting the broker
+    pub const fn cached_metadata(&self) -> Option<&CachedMetadata> {
+        self.metadata.as_ref()
+    }
+
+    /// Appends record batches to partitions led by this broker
+    ///
+    /// Per-partition errors and assigned base offsets are left in the
+    /// response. With [`Acks::None`] the broker never answers, so the request
+    /// is only written and the response has no partitions.
+    pub fn produce(&mut self, request: &ProduceRequest) -> Result<ProduceResponse> {
+        if request.acks == Acks::None {
+            let version = self.negotiate_version(ApiKey::Produce, produce::VERSIONS)?;
+            let body = produce::encode_request(version, request);
+            self.connection
+                .send_without_response(ApiKey::Produce.as_i16(), version, &body)?;
+            return Ok(ProduceResponse::default());
+        }
+
+        let response = self.send_versioned(
+            ApiKey::Produce,
+            produce::VERSIONS,
+            |version| produce::encode_request(version, request),
+            produce::decode_response,
+        )?;
+        self.report_throttle(ApiKey::Produce, response.throttle_time_ms);
+        Ok(response)
+    }
+
+    /// Reads record batches from partitions led by this broker
+    ///
+    /// Per-partition errors are left in the response; a top-level error,
+    /// such as a fetch session error, fails the whole call.
+    pub fn fetch(&mut self, request: &FetchRequest) -> Result<FetchResponse> {
+        let response = self.send_versioned(
+            ApiKey::Fetch,
+            fetch::VERSIONS,
+            |version| fetch::encode_request(version, request),
+            fetch::decode_response,
+        )?;
+        self.report_throttle(ApiKey::Fetch, response.throttle_time_ms);
+        if let Some(code) = ErrorCode::from_i16(response.error_code) {
+            return Err(self.fatal(KafkaError::Broker(code)));
+        }
+        Ok(response)
+    }
+
+    /// Reads the records of one partition starting at `offset`
+    ///
+    /// Returns at most `max_bytes` worth of batches, skipping transaction
+    /// markers and the records before `offset` that share its batch. An empty
+    /// result means nothing was written past `offset` yet. Reading stops
+    /// before a batch whose codec is not compiled in, and fails with
+    /// [`KafkaError::UnsupportedCodec`] when `offset` is inside one. With a batch cache
+    /// enabled, the request is answered without a round trip when consecutive
+    /// cached batches fill `max_bytes`, giving the same records a fetch would.
+    pub fn fetch_records(
+        &mut self,
+        partition: &TopicPartition,
+        offset: i64,
+        max_bytes: i32,
+    ) -> Result<Vec<Record>> {
+        if let Some(batches) = self.batch_cache.as_mut().and_then(|cache| {
+            cache.read(partition, offset, usize::try_from(max_bytes).unwrap_or(0))
+        }) {
+            return records_from(partition, offset, batches);
+        }
+
+        let mut request = FetchRequest {
+            max_bytes,
+            ..FetchRequest::default()
+        };
+        request.partitions.insert(
+            partition.clone(),
+            FetchPosition {
+                fetch_offset: offset,
+                partition_max_bytes: max_bytes,
+            },
+        );
+
+        let mut response = self.fetch(&request)?;
+        let Some(data) = response.partitions.remove(partition) else {
+            return Ok(Vec::new());
+        };
+        if let Some(code) = data.error() {
+            if code == ErrorCode::OffsetOutOfRange
+                && let Some(cache) = &mut self.batch_cache
+            {
+                cache.invalidate(partition);
+            }
+            return Err(self.fatal(KafkaError::Broker(code)));
+        }
+
+        let batches = data.sized_batches()?;
+        if let Some(cache) = &mut self.batch_cache {
+            for (batch, size) in &batches {
+                cache.insert(partition, batch.clone(), *size);
+            }
+        }
+        records_from(
+            partition,
+            offset,
+            batches.into_iter().map(|(batch, _)| batch),
+        )
+    }
+
+    /// Keeps up to `capacity` decoded batches for re-reads through
+    /// [`fetch_records`](Self::fetch_records); 0 disables and drops the cache
+    ///
+    /// Meant for tools that read the same offsets repeatedly. Cached batches
+    /// are not refreshed, so this should not be used where a partition's log
+    /// may be truncated under the reader.
+    pub fn set_batch_cache(&mut self, capacity: usize) {
+        self.batch_cache = (capacity > 0).then(|| BatchCache::new(capacity));
+    }
+
+    /// The batch cache, if enabled, e.g. for its hit rate
+    pub const fn batch_cache(&self) -> Option<&BatchCache> {
+        self.batch_cache.as_ref()
+    }
+
+    /// Looks up the coordinator of a group or transactional id, reusing an earlier answer
+    ///
+    /// Coordinators rarely move, so answers are cached per key until
+    /// [`coordinator_failed`](Self::coordinator_failed) sees NOT_COORDINATOR.
+    pub fn find_coordinator(
+        &mut self,
+        key_type: CoordinatorType,
+        key: &str,
+    ) -> Result<Coordinator> {
+        let cache_key = (key_type, key.to_string());
+        if let Some(coordinator) = self.coordinators.get(&cache_key) {
+            return Ok(coordinator.clone());
+        }
+
+        let versions = match key_type {
+            CoordinatorType::Group => find_coordinator::VERSIONS,
+            CoordinatorType::Transaction => VersionRange::new(1, find_coordinator::VERSIONS.max),
+        };
+        let response = self.send_versioned(
+            ApiKey::FindCoordinator,
+            versions,
+            |version| find_coordinator::encode_request(version, key_type, key),
+            find_coordinator::decode_response,
+        )?;
+        self.report_throttle(ApiKey::FindCoordinator, response.throttle_time_ms);
+        if let Some(code) = ErrorCode::from_i16(response.error_code) {
+            return Err(self.fatal(KafkaError::Broker(code)));
+        }
+
+        self.coordinators
+            .insert(cache_key, response.coordinator.clone());
+        Ok(response.coordinator)
+    }
+
+    /// Forgets the cached coordinator of a key
+    pub fn invalidate_coordinator(&mut self, key_type: CoordinatorType, key: &str) {
+        self.coordinators.remove(&(key_type, key.to_string()));
+    }
+
+    /// Forgets the cached coordinator if `error` says it moved, returning whether it did
+    pub fn coordinator_failed(
+        &mut self,
+        key_type: CoordinatorType,
+        key: &str,
+        error: &KafkaError,
+    ) -> bool {
+        let moved = matches!(
+            error.error_code(),
+            Some(ErrorCode::NotCoordinator | ErrorCode::CoordinatorNotAvailable)
+        );
+        if moved {
+            self.invalidate_coordinator(key_type, key);
+        }
+        moved
+    }
+
+    /// Sends a pre-encoded request body and returns the raw response body
+    ///
+    /// Meant for APIs the client does not wrap yet. The version is checked
+    /// against the range the broker advertised, and the throttle time is
+    /// reported for APIs whose responses lead with it. Headers are added and
+    /// stripped by the connection, so `body` and the result hold only the
+    /// message itself.
+    pub fn send_raw(&mut self, api_key: ApiKey, version: i16, body: &[u8]) -> Result<Vec<u8>> {
+        if !self.supports(api_key, version) {
+            return Err(KafkaError::UnsupportedVersion {
+                api_key: api_key.as_i16(),
+                version,
+            });
+        }
+
+        let response = self.round_trip(api_key.as_i16(), version, body)?;
+        if api_key.leading_throttle(version)
+            && let Ok(throttle_time_ms) = Cursor::new(&response).read()
+        {
+            self.report_throttle(api_key, throttle_time_ms);
+        }
+        Ok(response)
+    }
+
+    /// Sends one request and waits for its response, reporting lost connections
+    ///
+    /// A connection that lost sync is reset, and the request is sent again
+    /// if its API is [idempotent](ApiKey::is_idempotent).
+    fn round_trip(&mut self, api_key: i16, version: i16, body: &[u8]) -> Result<Vec<u8>> {
+        let error = match self.exchange(api_key, version, body) {
+            Err(error)
+                if self.connection.is_desynchronized() && self.connection.can_reconnect() =>
+            {
+                error
+            }
+            other => return other.map_err(|error| self.fatal(error)),
+        };
+
+        self.connection
+            .reset()
+            .map_err(|reset_error| self.fatal(reset_error))?;
+        if let Some(peer) = self.connection.peer() {
+            self.events.connection_established(peer);
+        }
+        if !ApiKey::from_i16(api_key).is_some_and(ApiKey::is_idempotent) {
+            return Err(self.fatal(error));
+        }
+        self.exchange(api_key, version, body)
+            .map_err(|error| self.fatal(error))
+    }
+
+    /// Sends one request and reads its response, without any recovery
+    fn exchange(&mut self, api_key: i16, version: i16, body: &[u8]) -> Result<Vec<u8>> {
+        let result = self
+            .connection
+            .send_request(api_key, version, body)
+            .and_then(|correlation_id| self.connection.receive_response(correlation_id));
+
+        if let Err(error) = &result
+            && (self.connection.is_desynchronized()
+                || matches!(
+                    error,
+                    KafkaError::Io(_)
+                        | KafkaError::Timeout {
+                            kind: TimeoutKind::Request,
+                            ..
+                        }
+                ))
+        {
+            self.events.connection_lost(self.connection.peer(), error);
+        }
+        result
+    }
+
+    /// Reports `error` to the event receiver if it is fatal, and passes it on
+    fn fatal(&self, error: KafkaError) -> KafkaError {
+        if matches!(error.kind(), ErrorKind::Fatal | ErrorKind::Serialization) {
+            self.events.fatal_error(&error);
+        }
+        error
+    }
+
+    /// Tells the event receiver about a non-zero throttle time from a response
+    fn report_throttle(&self, api_key: ApiKey, throttle_time_ms: i32) {
+        if throttle_time_ms > 0 {
+            let throttle = Duration::from_millis(throttle_time_ms as u64);
+            self.events
+                .request_throttled(self.connection.peer(), api_key, throttle);
+        }
+    }
+}
+
+/// Whether a bootstrap failure may go away by trying again later
+fn is_unreachable(error: &KafkaError) -> bool {
+    matches!(
+        error,
+        KafkaError::Io(_)
+            | KafkaError::Timeout {
+                kind: TimeoutKind::Connect,
+                ..
+            }
+    )
+}
+
+/// Application records of `batches` at or after `offset`, skipping transaction markers
+///
+/// Stops at the first batch left compressed holding wanted records, which fails
+/// with [`KafkaError::UnsupportedCodec`] only if nothing precedes it.
+fn records_from(
+    partition: &TopicPartition,
+    offset: i64,
+    batches: impl IntoIterator<Item = RecordBatch>,
+) -> Result<Vec<Record>> {
+    let mut records = Vec::new();
+    for batch in batches {
+        if batch.is_control() || batch.last_offset() < offset {
+            continue;
+        }
+        if batch.is_compressed() && batch.records.is_empty() {
+            if records.is_empty() {
+                return Err(KafkaError::UnsupportedCodec {
+                    codec: batch.compression,
+                    partition: partition.clone(),
+                });
+            }
+            break;
+        }
+        records.extend(
+            batch
+                .records
+                .into_iter()
+                .filter(|record| record.offset >= offset),
+        );
+    }
+    Ok(records)
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::connection::tests::{Echo, Handler, scripted_connection};
+
+    /// Highest Metadata version the broker in [`old_broker`] accepts
+    const METADATA_MAX: i16 = 5;
+
+    /// A broker offering ApiVersions v0 and Metadata v0-v8 that fails any
+    /// Metadata request above [`METADATA_MAX`] with UNSUPPORTED_VERSION
+    pub(crate) fn old_broker(api_key: i16, version: i16, _body: &[u8]) -> Vec<u8> {
+        let mut body = Vec::new();
+        if api_key == ApiKey::ApiVersions.as_i16() {
+            let (error_code, ranges): (i16, &[(i16, i16, i16)]) = if version > 0 {
+                (35, &[(18, 0, 0)])
+            } else {
+                (0, &[(18, 0, 0), (3, 0, 8)])
+            };
+            body.extend(error_code.to_be_bytes());
+            body.extend((ranges.len() as i32).to_be_bytes());
+            for &(key, min, max) in ranges {
+                body.extend(key.to_be_bytes());
+                body.extend(min.to_be_bytes());
+                body.extend(max.to_be_bytes());
+            }
+            return body;
+        }
+
+        // A Metadata response with no brokers and one partitionless topic
+        let error_code: i16 = if version > METADATA_MAX { 35 } else { 0 };
+        if version >= 3 {
+            body.extend(0i32.to_be_bytes()); // throttle_time_ms
+        }
+        body.extend(0i32.to_be_bytes()); // brokers
+        if version >= 2 {
+            body.extend((-1i16).to_be_bytes()); // cluster_id
+        }
+        if version >= 1 {
+            body.extend((-1i32).to_be_bytes()); // controller_id
+        }
+        body.extend(1i32.to_be_bytes());
+        body.extend(error_code.to_be_bytes());
+        body.extend(1i16.to_be_bytes());
+        body.push(b't');
+        if version >= 1 {
+            body.push(0); // is_internal
+        }
+        body.extend(0i32.to_be_bytes()); // partitions
+        if version >= 8 {
+            body.extend(i32::MIN.to_be_bytes()); // topic_authorized_operations
+            body.extend(i32::MIN.to_be_bytes()); // cluster_authorized_operations
+        }
+        body
+    }
+
+    fn client(handler: Handler) -> KafkaClient<Echo> {
+        KafkaClient::from_connection(scripted_connection(handler)).unwrap()
+    }
+
+    #[test]
+    fn unsupported_version_in_response_downgrades() {
+        let mut client = client(old_broker);
+        let metadata = client.fetch_metadata(&["t"]).unwrap();
+        assert_eq!(metadata.topics[0].name.as_deref(), Some("t"));
+
+        let metadata_versions: Vec<_> = client
+            .connection
+            .get_ref()
+            .requests
+            .iter()
+            .filter(|&&(api_key, _)| api_key == ApiKey::Metadata.as_i16())
+            .map(|&(_, version)| version)
+            .collect();
+        assert_eq!(metadata_versions, [8, 7, 6, 5]);
+    }
+
+    #[test]
+    fn metadata_updates_are_reported() {
+        #[derive(Default)]
+        struct Topics(std::sync::Mutex<Vec<usize>>);
+
+        impl ClientEvents for Topics {
+            fn metadata_updated(&self, metadata: &MetadataResponse) {
+                self.0.lock().unwrap().push(metadata.topics.len());
+            }
+        }
+
+        let events = Arc::new(Topics::default());
+        let mut client = KafkaClient::from_connection_with_events(
+            scripted_connection(old_broker),
+            events.clone(),
+        )
+        .unwrap();
+        assert!(events.0.lock().unwrap().is_empty());
+        client.fetch_metadata(&["t"]).unwrap();
+        client.fetch_metadata(&["t"]).unwrap();
+        assert_eq!(*events.0.lock().unwrap(), [1, 1]);
+    }
+
+    #[test]
+    fn unsupported_version_below_client_range_fails() {
+        let mut client = client(old_broker);
+        let error = client
+            .send_versioned(
+                ApiKey::Metadata,
+                VersionRange::new(6, 8),
+                |version| metadata::encode_request(version, Some(&["t"])),
+                metadata::decode_response,
+            )
+            .unwrap_err();
+        assert!(matches!(
+            error,
+            KafkaError::UnsupportedVersion { version: 6, .. }
+        ));
+    }
+}
@@ -0,0 +1,79 @@
+//! Registry of named clusters for applications that talk to more than one.
+
+use std::collections::HashMap;
+use std::collections::hash_map::Entry;
+
+use crate::client::KafkaClient;
+use crate::config::ClientConfig;
+use crate::error::{KafkaError, Result};
+
+/// Named cluster configurations with lazily connected clients
+///
+/// Clusters are registered under a name such as `"prod-eu"`; the client for
+/// a cluster is only connected the first time it is asked for, and then
+/// reused.
+#[derive(Default)]
+pub struct KafkaClusters {
+    configs: HashMap<String, ClientConfig>,
+    clients: HashMap<String, KafkaClient>,
+}
+
+impl KafkaClusters {
+    /// Creates an empty registry
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Registers a cluster, returning the configuration it replaced
+    ///
+    /// A client already connected under this name is dropped, so the next
+    /// lookup connects with the new configuration.
+    pub fn register(
+        &mut self,
+        name: impl Into<String>,
+        config: ClientConfig,
+    ) -> Option<ClientConfig> {
+        let name = name.into();
+        self.clients.remove(&name);
+        self.configs.insert(name, config)
+    }
+
+    /// Returns the client for a cluster, connecting it on first use
+    pub fn client(&mut self, name: &str) -> Result<&mut KafkaClient> {
+        let config = self
+            .configs
+            .get(name)
+            .ok_or_else(|| KafkaError::Config(format!("unknown cluster `{name}`")))?;
+
+        match self.clients.entry(name.to_string()) {
+            Entry::Occupied(entry) => Ok(entry.into_mut()),
+            Entry::Vacant(entry) => Ok(entry.insert(KafkaClient::from_config(config)?)),
+        }
+    }
+
+    /// Configuration registered under a name
+    pub fn config(&self, name: &str) -> Option<&ClientConfig> {
+        self.configs.get(name)
+    }
+
+    /// Names of all registered clusters
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.configs.keys().map(String::as_str)
+    }
+
+    /// Whether the client for a cluster is currently connected
+    pub fn is_connected(&self, name: &str) -> bool {
+        self.clients.contains_key(name)
+    }
+
+    /// Drops the client of a cluster; the next lookup reconnects
+    pub fn disconnect(&mut self, name: &str) -> bool {
+        self.clients.remove(name).is_some()
+    }
+
+    /// Removes a cluster and its client, returning its configuration
+    pub fn remove(&mut self, name: &str) -> Option<ClientConfig> {
+        self.clients.remove(name);
+        self.configs.remove(name)
+    }
+}
@@ -0,0 +1,591 @@
+//! Framed request/response transport for a single broker connection.
+//!
+//! Every request is written as a size-prefixed frame carrying a fresh
+//! correlation id. Requests may be pipelined: the broker answers them in
+//! order, so responses nobody waited for are skipped while reading, and the
+//! connection only gives up when a correlation id it never sent shows up.
+//! Such a connection refuses further requests until it is reset.
+
+use std::collections::VecDeque;
+use std::io::{self, Read, Write};
+use std::net::{SocketAddr, TcpStream, ToSocketAddrs};
+use std::time::{Duration, Instant};
+
+use crate::auth::SaslCredentials;
+use crate::error::{KafkaError, Result, TimeoutKind};
+use crate::net::{self, SocketConfig};
+use crate::protocol::ApiKey;
+use crate::protocol::codec::{Cursor, Encode};
+use crate::protocol::header::{RequestHeader, ResponseHeader};
+
+/// Size of the length prefix in front of every frame
+const SIZE_PREFIX: usize = 4;
+
+/// Bytes requested from the socket per read call
+const READ_CHUNK: usize = 8 * 1024;
+
+/// Largest response frame accepted, the broker's default `socket.request.max.bytes`;
+/// a bigger size prefix means the frame boundary was lost
+const MAX_FRAME_SIZE: usize = 100 * 1024 * 1024;
+
+/// Low-level broker connection for issuing arbitrary requests with raw encoded bodies
+pub type BrokerConnection<S = TcpStream> = Connection<S>;
+
+/// A connection to one broker, tracking requests that still await a response
+pub struct Connection<S = TcpStream> {
+    stream: S,
+    /// Address to reconnect to when the stream has to be reset
+    peer: Option<SocketAddr>,
+    /// Socket settings used when (re)connecting, if this is a TCP connection
+    socket: Option<SocketConfig>,
+    client_id: String,
+    next_correlation_id: i32,
+    /// Sent requests awaiting a response, oldest first
+    in_flight: VecDeque<InFlightRequest>,
+    /// Bytes read from the socket that do not form a complete frame yet
+    read_buf: Vec<u8>,
+    bytes_sent: u64,
+    bytes_received: u64,
+    /// When a frame was last written or bytes were last read
+    last_activity: Option<Instant>,
+    /// Credentials the connection authenticated with, reused after a reset
+    credentials: Option<SaslCredentials>,
+    /// Opens a new stream to `peer`, if the stream type supports reconnecting
+    reopen: Option<fn(SocketAddr, &SocketConfig) -> io::Result<S>>,
+    /// Set once the frame boundary is lost; only a reset clears it
+    desynchronized: bool,
+}
+
+/// A request that was sent and awaits its response
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct InFlightRequest {
+    pub correlation_id: i32,
+    pub api_key: i16,
+    pub api_version: i16,
+}
+
+impl InFlightRequest {
+    /// Header version the response to this request arrives with
+    fn response_header_version(&self) -> i16 {
+        ApiKey::from_i16(self.api_key)
+            .map_or(0, |key| key.response_header_version(self.api_version))
+    }
+}
+
+/// Snapshot of a connection's state for diagnosing stuck pipelines
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct ConnectionDump {
+    pub peer: Option<SocketAddr>,
+    pub client_id: String,
+    /// Requests awaiting a response, oldest first
+    pub in_flight: Vec<InFlightRequest>,
+    /// Received bytes buffered while waiting for the rest of a frame
+    pub buffered_bytes: usize,
+    pub bytes_sent: u64,
+    pub bytes_received: u64,
+    /// Time since a frame was last written or bytes were last read
+    pub idle: Option<Duration>,
+    /// SASL mechanism the connection authenticated with
+    pub authenticated: Option<String>,
+}
+
+impl Connection<TcpStream> {
+    /// Opens a TCP connection to the broker with default socket settings
+    pub fn connect(addr: impl ToSocketAddrs, client_id: &str) -> Result<Self> {
+        Self::connect_with(addr, client_id, SocketConfig::default())
+    }
+
+    /// Opens a TCP connection to the broker with the given socket settings
+    pub fn connect_with(
+        addr: impl ToSocketAddrs,
+        client_id: &str,
+        socket: SocketConfig,
+    ) -> Result<Self> {
+        let addresses: Vec<SocketAddr> = addr.to_socket_addrs()?.collect();
+        let stream = net::connect(addresses.as_slice(), &socket)
+            .map_err(|e| connect_error(e, &addresses, socket.connect_timeout))?;
+
+        let mut connection = Self::new(stream, client_id);
+        connection.peer = Some(connection.stream.peer_addr()?);
+        connection.socket = Some(socket);
+        connection.reopen = Some(net::connect_address);
+        Ok(connection)
+    }
+}
+
+impl<S: Read + Write> Connection<S> {
+    /// Wraps an already established stream
+    pub fn new(stream: S, client_id: &str) -> Self {
+        Self {
+            stream,
+            peer: None,
+            socket: None,
+            client_id: client_id.to_string(),
+            next_correlation_id: 0,
+            in_flight: VecDeque::new(),
+            read_buf: Vec::new(),
+            bytes_sent: 0,
+            bytes_received: 0,
+            last_activity: None,
+            credentials: None,
+            reopen: None,
+            desynchronized: false,
+        }
+    }
+
+    /// Drops the stream and reconnects, forgetting every in-flight request
+    ///
+    /// Only connections opened from an address can reconnect. A connection
+    /// that was authenticated authenticates again.
+    pub fn reset(&mut self) -> Result<()> {
+        let (Some(peer), Some(reopen)) = (self.peer, self.reopen) else {
+            return Err(KafkaError::ProtocolError(
+                "connection has no peer address to reconnect to".into(),
+            ));
+        };
+
+        let socket = self.socket.clone().unwrap_or_default();
+        self.stream =
+            reopen(peer, &socket).map_err(|e| connect_error(e, &[peer], socket.connect_timeout))?;
+        self.in_flight.clear();
+        self.read_buf.clear();
+        self.desynchronized = false;
+        // Kept in place, so a failed re-authentication can be retried by another reset
+        if let Some(credentials) = self.credentials.clone() {
+            self.authenticate(&credentials)?;
+        }
+        Ok(())
+    }
+
+    /// Whether [`reset`](Self::reset) can open a new stream, which needs a
+    /// connection opened from an address
+    pub const fn can_reconnect(&self) -> bool {
+        self.peer.is_some() && self.reopen.is_some()
+    }
+
+    /// Whether the frame boundary was lost, so the connection needs a [`reset`](Self::reset)
+    pub const fn is_desynchronized(&self) -> bool {
+        self.desynchronized
+    }
+
+    /// Address of the broker, if the connection was opened from one
+    pub const fn peer(&self) -> Option<SocketAddr> {
+        self.peer
+    }
+
+    /// Returns a reference to the underlying stream
+    pub const fn get_ref(&self) -> &S {
+        &self.stream
+    }
+
+    /// Consumes the connection, returning the underlying stream
+    pub fn into_inner(self) -> S {
+        self.stream
+    }
+
+    /// Number of requests sent whose response has not been read yet
+    pub fn in_flight(&self) -> usize {
+        self.in_flight.len()
+    }
+
+    /// Captures the connection's counters and in-flight requests
+    pub fn debug_dump(&self) -> ConnectionDump {
+        ConnectionDump {
+            peer: self.peer,
+            client_id: self.client_id.clone(),
+            in_flight: self.in_flight.iter().copied().collect(),
+            buffered_bytes: self.read_buf.len(),
+            bytes_sent: self.bytes_sent,
+            bytes_received: self.bytes_received,
+            idle: self.last_activity.map(|at| at.elapsed()),
+            authenticated: self
+                .credentials
+                .as_ref()
+                .map(|credentials| credentials.mechanism.to_string()),
+        }
+    }
+
+    /// Writes a request frame and returns the correlation id it was sent with
+    pub fn send_request(&mut self, api_key: i16, api_version: i16, body: &[u8]) -> Result<i32> {
+        let correlation_id = self.write_request(api_key, api_version, body)?;
+        self.in_flight.push_back(InFlightRequest {
+            correlation_id,
+            api_key,
+            api_version,
+        });
+        Ok(correlation_id)
+    }
+
+    /// Writes a request the broker does not answer, such as a Produce with acks=0
+    ///
+    /// The request is not tracked as in flight, so reading the responses to
+    /// other requests is unaffected.
+    pub fn send_without_response(
+        &mut self,
+        api_key: i16,
+        api_version: i16,
+        body: &[u8],
+    ) -> Result<()> {
+        self.write_request(api_key, api_version, body).map(drop)
+    }
+
+    /// Writes one request frame and returns its correlation id
+    fn write_request(&mut self, api_key: i16, api_version: i16, body: &[u8]) -> Result<i32> {
+        if self.desynchronized {
+            return Err(KafkaError::ProtocolError(
+                "connection lost sync with the broker and must be reset".into(),
+            ));
+        }
+        let correlation_id = self.next_correlation_id;
+        self.next_correlation_id = self.next_correlation_id.wrapping_add(1);
+
+        if i16::try_from(self.client_id.len()).is_err() {
+            return Err(KafkaError::Serialization("client id is too long".into()));
+        }
+        let header = RequestHeader::new(api_key, api_version, correlation_id, &self.client_id);
+
+        let mut frame = Vec::with_capacity(SIZE_PREFIX + 11 + self.client_id.len() + body.len());
+        frame.extend_from_slice(&[0; SIZE_PREFIX]); // Overwritten with the actual length below
+        header.encode(&mut frame);
+        frame.extend_from_slice(body);
+
+        let len = i32::try_from(frame.len() - SIZE_PREFIX)
+            .map_err(|_| KafkaError::Serialization("request is too large".into()))?;
+        frame[..SIZE_PREFIX].copy_from_slice(&len.to_be_bytes());
+
+        self.stream
+            .write_all(&frame)
+            .and_then(|()| self.stream.flush())
+            .map_err(|e| {
+                KafkaError::from_io(
+                    e,
+                    TimeoutKind::Request,
+                    || format!("sending a request for API key {api_key}"),
+                    self.socket.as_ref().and_then(|socket| socket.write_timeout),
+                )
+            })?;
+        self.bytes_sent += frame.len() as u64;
+        self.last_activity = Some(Instant::now());
+        Ok(correlation_id)
+    }
+
+    /// Reads responses until the one for `correlation_id` arrives and returns its body
+    ///
+    /// Responses to older pipelined requests that are still unread get
+    /// discarded on the way, so a caller that abandoned a response does not
+    /// poison the requests queued behind it. The response header, including
+    /// the tagged fields of flexible versions, is stripped from the body.
+    pub fn receive_response(&mut self, correlation_id: i32) -> Result<Vec<u8>> {
+        let Some(&request) = self
+            .in_flight
+            .iter()
+            .find(|request| request.correlation_id == correlation_id)
+        else {
+            return Err(KafkaError::ProtocolError(format!(
+                "no request in flight with correlation id {correlation_id}"
+            )));
+        };
+
+        loop {
+            let frame = self.read_frame().map_err(|e| match e {
+                KafkaError::Io(e) => KafkaError::from_io(
+                    e,
+                    TimeoutKind::Request,
+                    || {
+                        format!(
+                            "waiting for the response to API key {} \
+                             (correlation id {correlation_id})",
+                            request.api_key
+                        )
+                    },
+                    self.socket.as_ref().and_then(|socket| socket.read_timeout),
+                ),
+                other => other,
+            })?;
+            // Brokers answer in request order, so the oldest in-flight request is the only valid one
+            let expected = self.in_flight.pop_front().unwrap_or(request);
+            let mut cursor = Cursor::new(&frame);
+            let Ok(header) =
+                ResponseHeader::decode(&mut cursor, expected.response_header_version())
+            else {
+                return Err(self.desynchronized(KafkaError::ProtocolError(
+                    "response frame is shorter than its header".into(),
+                )));
+            };
+
+            let received = header.correlation_id;
+            if received != expected.correlation_id {
+                return Err(self.desynchronized(KafkaError::CorrelationMismatch {
+                    expected: expected.correlation_id,
+                    received,
+                }));
+            }
+
+            if received == correlation_id {
+                return Ok(frame[cursor.position()..].to_vec());
+            }
+            // A response to an earlier request nobody waited for; skip it
+        }
+    }
+
+    /// Remembers the credentials a successful authentication used
+    pub(crate) fn set_authenticated(&mut self, credentials: SaslCredentials) {
+        self.credentials = Some(credentials);
+    }
+
+    /// Forgets all connection state once the frame boundary can no longer be trusted
+    fn desynchronized(&mut self, error: KafkaError) -> KafkaError {
+        self.in_flight.clear();
+        self.read_buf.clear();
+        self.desynchronized = true;
+        error
+    }
+
+    /// Reads one complete frame, keeping partial data buffered across failed reads
+    fn read_frame(&mut self) -> Result<Vec<u8>> {
+        loop {
+            if let Some(frame) = self.take_buffered_frame()? {
+                return Ok(frame);
+            }
+
+            let mut chunk = [0u8; READ_CHUNK];
+            let n = match self.stream.read(&mut chunk) {
+                Ok(0) => return Err(io::Error::from(io::ErrorKind::UnexpectedEof).into()),
+                Ok(n) => n,
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                // Timeouts leave the partial frame in `read_buf` so the next read resumes it
+                Err(e) => return Err(e.into()),
+            };
+            self.read_buf.extend_from_slice(&chunk[..n]);
+            self.bytes_received += n as u64;
+            self.last_activity = Some(Instant::now());
+        }
+    }
+
+    /// Splits a complete frame off the front of the read buffer, if there is one
+    fn take_buffered_frame(&mut self) -> Result<Option<Vec<u8>>> {
+        let Some(prefix) = self.read_buf.first_chunk::<SIZE_PREFIX>() else {
+            return Ok(None);
+        };
+
+        let size = i32::from_be_bytes(*prefix);
+        let Ok(size) = usize::try_from(size) else {
+            return Err(self.desynchronized(KafkaError::ProtocolError(format!(
+                "negative frame size {size}"
+            ))));
+        };
+        if size > MAX_FRAME_SIZE {
+            return Err(self.desynchronized(KafkaError::ProtocolError(format!(
+                "frame size {size} exceeds the maximum of {MAX_FRAME_SIZE}"
+            ))));
+        }
+
+        let end = SIZE_PREFIX + size;
+        if self.read_buf.len() < end {
+            return Ok(None);
+        }
+
+        let frame = self.read_buf[SIZE_PREFIX..end].to_vec();
+        self.read_buf.drain(..end);
+        Ok(Some(frame))
+    }
+}
+
+/// Describes a failed connection attempt, naming the addresses that were tried
+fn connect_error(error: io::Error, addresses: &[SocketAddr], limit: Duration) -> KafkaError {
+    KafkaError::from_io(
+        error,
+        TimeoutKind::Connect,
+        || match addresses {
+            [address] => format!("connecting to {address}"),
+            _ => format!("connecting to any of {addresses:?}"),
+        },
+        Some(limit),
+    )
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    /// Metadata v1, whose responses use header v0
+    const API_KEY: i16 = 3;
+    const API_VERSION: i16 = 1;
+
+    /// Builds a response body from the API key, version and request body
+    pub(crate) type Handler = fn(i16, i16, &[u8]) -> Vec<u8>;
+
+    /// An in-memory broker answering every request with its correlation id and body
+    ///
+    /// With a `handler` the response body is built by it instead. Extra
+    /// frames can be queued in front of the answers to simulate a
+    /// misbehaving broker. Reading with nothing queued fails with `WouldBlock`.
+    #[derive(Debug, Default)]
+    pub(crate) struct Echo {
+        pub(crate) responses: VecDeque<u8>,
+        /// API key and version of every request received
+        pub(crate) requests: Vec<(i16, i16)>,
+        pub(crate) handler: Option<Handler>,
+    }
+
+    impl Echo {
+        /// Queues a response frame with header v0, `correlation_id` and `body`
+        pub(crate) fn respond(&mut self, correlation_id: i32, body: &[u8]) {
+            self.respond_with_header(0, correlation_id, body);
+        }
+
+        fn respond_with_header(&mut self, header_version: i16, correlation_id: i32, body: &[u8]) {
+            let tagged_fields: &[u8] = if header_version >= 1 { &[0] } else { &[] };
+            let size = 4 + tagged_fields.len() + body.len();
+            self.responses.extend((size as i32).to_be_bytes());
+            self.responses.extend(correlation_id.to_be_bytes());
+            self.responses.extend(tagged_fields);
+            self.responses.extend(body);
+        }
+    }
+
+    impl Read for Echo {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            if self.responses.is_empty() && !buf.is_empty() {
+                return Err(io::ErrorKind::WouldBlock.into());
+            }
+            self.responses.read(buf)
+        }
+    }
+
+    impl Write for Echo {
+        /// Expects one whole request frame per call, as `write_request` writes them
+        fn write(&mut self, frame: &[u8]) -> io::Result<usize> {
+            let api_key = i16::from_be_bytes([frame[4], frame[5]]);
+            let version = i16::from_be_bytes([frame[6], frame[7]]);
+            let correlation_id = i32::from_be_bytes(frame[8..12].try_into().expect("4 bytes"));
+            let client_id_len = i16::from_be_bytes([frame[12], frame[13]]).max(0) as usize;
+            let key = ApiKey::from_i16(api_key);
+            // Flexible request headers end with empty tagged fields
+            let flexible = key.is_some_and(|key| key.is_flexible(version));
+            let body = &frame[14 + client_id_len + usize::from(flexible)..];
+
+            self.requests.push((api_key, version));
+            let response = match self.handler {
+                Some(handler) => handler(api_key, version, body),
+                None => body.to_vec(),
+            };
+            let header_version = key.map_or(0, |key| key.response_header_version(version));
+            self.respond_with_header(header_version, correlation_id, &response);
+            Ok(frame.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    /// A connection to an [`Echo`] that reconnects to a fresh one
+    pub(crate) fn echo_connection() -> Connection<Echo> {
+        reconnecting(Echo::default(), |_, _| Ok(Echo::default()))
+    }
+
+    /// A connection over `stream` that reconnects through `reopen`
+    pub(crate) fn reconnecting<S: Read + Write>(
+        stream: S,
+        reopen: fn(SocketAddr, &SocketConfig) -> io::Result<S>,
+    ) -> Connection<S> {
+        let mut connection = Connection::new(stream, "test");
+        connection.peer = Some(SocketAddr::from(([127, 0, 0, 1], 9092)));
+        connection.reopen = Some(reopen);
+        connection
+    }
+
+    /// A connection to an [`Echo`] answering through `handler`
+    pub(crate) fn scripted_connection(handler: Handler) -> Connection<Echo> {
+        let mut connection = echo_connection();
+        connection.stream.handler = Some(handler);
+        connection
+    }
+
+    #[test]
+    fn stale_responses_are_skipped() {
+        let mut connection = echo_connection();
+        let first = connection
+            .send_request(API_KEY, API_VERSION, b"first")
+            .unwrap();
+        let second = connection
+            .send_request(API_KEY, API_VERSION, b"second")
+            .unwrap();
+        assert_eq!(connection.in_flight(), 2);
+
+        assert_eq!(connection.receive_response(second).unwrap(), b"second");
+        assert_eq!(connection.in_flight(), 0);
+        assert!(!connection.is_desynchronized());
+        assert!(connection.receive_response(first).is_err());
+    }
+
+    #[test]
+    fn correlation_mismatch_desynchronizes_until_reset() {
+        let mut connection = echo_connection();
+        connection.stream.respond(42, b"stray");
+        let id = connection
+            .send_request(API_KEY, API_VERSION, b"body")
+            .unwrap();
+
+        let error = connection.receive_response(id).unwrap_err();
+        assert!(matches!(
+            error,
+            KafkaError::CorrelationMismatch {
+                expected: 0,
+                received: 42
+            }
+        ));
+        assert!(connection.is_desynchronized());
+        assert_eq!(connection.in_flight(), 0);
+        assert!(
+            connection
+                .send_request(API_KEY, API_VERSION, b"body")
+                .is_err()
+        );
+
+        connection.reset().unwrap();
+        assert!(!connection.is_desynchronized());
+        let id = connection
+            .send_request(API_KEY, API_VERSION, b"again")
+            .unwrap();
+        assert_eq!(connection.receive_response(id).unwrap(), b"again");
+    }
+
+    #[test]
+    fn oversized_frames_are_rejected() {
+        let mut connection = echo_connection();
+        let size = i32::try_from(MAX_FRAME_SIZE + 1).unwrap();
+        connection.stream.responses.extend(size.to_be_bytes());
+        let id = connection
+            .send_request(API_KEY, API_VERSION, b"body")
+            .unwrap();
+
+        assert!(connection.receive_response(id).is_err());
+        assert!(connection.is_desynchronized());
+        assert_eq!(connection.debug_dump().buffered_bytes, 0);
+    }
+
+    #[test]
+    fn failed_reauthentication_keeps_credentials() {
+        let mut connection = echo_connection();
+        connection.set_authenticated(SaslCredentials::plain("user", "secret"));
+
+        // The echoed handshake is not a valid SaslHandshake response
+        assert!(connection.reset().is_err());
+        assert!(connection.reset().is_err());
+        assert_eq!(
+            connection.debug_dump().authenticated.as_deref(),
+            Some("PLAIN")
+        );
+    }
+
+    #[test]
+    fn reset_needs_an_address() {
+        let mut connection = Connection::new(Echo::default(), "test");
+        assert!(!connection.can_reconnect());
+        assert!(connection.reset().is_err());
+    }
+}
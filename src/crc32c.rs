@@ -0,0 +1,224 @@
+//! CRC32C (Castagnoli) checksums, as carried by every RecordBatch v2.
+//!
+//! Uses the SSE4.2 `crc32` instruction on x86_64 and the ARMv8 CRC
+//! extension on aarch64 when the running CPU has them, and a slicing-by-8
+//! table implementation everywhere else. The choice is made once, at the
+//! first call.
+
+use std::sync::OnceLock;
+
+/// Reversed Castagnoli polynomial
+const POLY: u32 = 0x82F6_3B78;
+
+/// Lookup tables for processing eight bytes per step
+static TABLES: [[u32; 256]; 8] = build_tables();
+
+/// Implementation picked for the running CPU
+static IMPLEMENTATION: OnceLock<Implementation> = OnceLock::new();
+
+/// Ways of computing the checksum
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Implementation {
+    Software,
+    #[cfg(target_arch = "x86_64")]
+    Sse42,
+    #[cfg(target_arch = "aarch64")]
+    Armv8,
+}
+
+/// Computes the CRC32C of `data`
+pub fn crc32c(data: &[u8]) -> u32 {
+    update(0, data)
+}
+
+/// Extends a checksum previously returned by [`crc32c`] or `update` with more data
+pub fn update(crc: u32, data: &[u8]) -> u32 {
+    let crc = !crc;
+    !match implementation() {
+        Implementation::Software => update_software(crc, data),
+        #[cfg(target_arch = "x86_64")]
+        Implementation::Sse42 => hardware::update_sse42(crc, data),
+        #[cfg(target_arch = "aarch64")]
+        Implementation::Armv8 => hardware::update_armv8(crc, data),
+    }
+}
+
+/// Whether checksums are computed with dedicated CPU instructions
+pub fn is_hardware_accelerated() -> bool {
+    implementation() != Implementation::Software
+}
+
+/// Picks the fastest implementation the running CPU supports, once
+fn implementation() -> Implementation {
+    *IMPLEMENTATION.get_or_init(|| {
+        #[cfg(target_arch = "x86_64")]
+        if std::arch::is_x86_feature_detected!("sse4.2") {
+            return Implementation::Sse42;
+        }
+
+        #[cfg(target_arch = "aarch64")]
+        if std::arch::is_aarch64_feature_detected!("crc") {
+            return Implementation::Armv8;
+        }
+
+        Implementation::Software
+    })
+}
+
+/// Table-driven CRC over a pre-inverted checksum
+fn update_software(mut crc: u32, data: &[u8]) -> u32 {
+    let mut chunks = data.chunks_exact(8);
+    for chunk in &mut chunks {
+        let low = crc ^ u32::from_le_bytes([chunk[0], chunk[1], chunk[2], chunk[3]]);
+        crc = TABLES[7][(low & 0xff) as usize]
+            ^ TABLES[6][((low >> 8) & 0xff) as usize]
+            ^ TABLES[5][((low >> 16) & 0xff) as usize]
+            ^ TABLES[4][(low >> 24) as usize]
+            ^ TABLES[3][chunk[4] as usize]
+            ^ TABLES[2][chunk[5] as usize]
+            ^ TABLES[1][chunk[6] as usize]
+            ^ TABLES[0][chunk[7] as usize];
+    }
+
+    for &byte in chunks.remainder() {
+        crc = TABLES[0][((crc ^ u32::from(byte)) & 0xff) as usize] ^ (crc >> 8);
+    }
+    crc
+}
+
+const fn build_tables() -> [[u32; 256]; 8] {
+    let mut tables = [[0u32; 256]; 8];
+
+    let mut i = 0;
+    while i < 256 {
+        let mut crc = i as u32;
+        let mut bit = 0;
+        while bit < 8 {
+            crc = if crc & 1 == 1 {
+                (crc >> 1) ^ POLY
+            } else {
+                crc >> 1
+            };
+            bit += 1;
+        }
+        tables[0][i] = crc;
+        i += 1;
+    }
+
+    let mut i = 0;
+    while i < 256 {
+        let mut table = 1;
+        while table < 8 {
+            let previous = tables[table - 1][i];
+            tables[table][i] = (previous >> 8) ^ tables[0][(previous & 0xff) as usize];
+            table += 1;
+        }
+        i += 1;
+    }
+    tables
+}
+
+#[cfg(any(target_arch = "x86_64", target_arch = "aarch64"))]
+mod hardware {
+    /// CRC over a pre-inverted checksum using SSE4.2
+    #[cfg(target_arch = "x86_64")]
+    pub(super) fn update_sse42(crc: u32, data: &[u8]) -> u32 {
+        // SAFETY: only selected after `is_x86_feature_detected!("sse4.2")` succeeded
+        unsafe { sse42(crc, data) }
+    }
+
+    #[cfg(target_arch = "x86_64")]
+    #[target_feature(enable = "sse4.2")]
+    unsafe fn sse42(crc: u32, data: &[u8]) -> u32 {
+        use std::arch::x86_64::{_mm_crc32_u8, _mm_crc32_u64};
+
+        let mut crc = u64::from(crc);
+        let mut chunks = data.chunks_exact(8);
+        for chunk in &mut chunks {
+            let word = u64::from_le_bytes(chunk.try_into().expect("chunk has 8 bytes"));
+            crc = _mm_crc32_u64(crc, word);
+        }
+
+        let mut crc = crc as u32;
+        for &byte in chunks.remainder() {
+            crc = _mm_crc32_u8(crc, byte);
+        }
+        crc
+    }
+
+    /// CRC over a pre-inverted checksum using the ARMv8 CRC extension
+    #[cfg(target_arch = "aarch64")]
+    pub(super) fn update_armv8(crc: u32, data: &[u8]) -> u32 {
+        // SAFETY: only selected after `is_aarch64_feature_detected!("crc")` succeeded
+        unsafe { armv8(crc, data) }
+    }
+
+    #[cfg(target_arch = "aarch64")]
+    #[target_feature(enable = "crc")]
+    unsafe fn armv8(mut crc: u32, data: &[u8]) -> u32 {
+        use std::arch::aarch64::{__crc32cb, __crc32cd};
+
+        let mut chunks = data.chunks_exact(8);
+        for chunk in &mut chunks {
+            let word = u64::from_le_bytes(chunk.try_into().expect("chunk has 8 bytes"));
+            crc = __crc32cd(crc, word);
+        }
+        for &byte in chunks.remainder() {
+            crc = __crc32cb(crc, byte);
+        }
+        crc
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Bit-at-a-time CRC32C, independent of both fast paths
+    fn bitwise(data: &[u8]) -> u32 {
+        !data.iter().fold(!0u32, |mut crc, &byte| {
+            crc ^= u32::from(byte);
+            for _ in 0..8 {
+                crc = if crc & 1 == 1 {
+                    (crc >> 1) ^ POLY
+                } else {
+                    crc >> 1
+                };
+            }
+            crc
+        })
+    }
+
+    fn data(len: usize) -> Vec<u8> {
+        (0..len).map(|i| (i * 31 + 7) as u8).collect()
+    }
+
+    #[test]
+    fn check_value() {
+        assert_eq!(crc32c(b"123456789"), 0xE306_9283);
+        assert_eq!(!update_software(!0, b"123456789"), 0xE306_9283);
+    }
+
+    #[test]
+    fn software_matches_selected_implementation() {
+        let large = data(64 * 1024 + 3);
+        for data in (0..=17).map(data).chain([large]) {
+            let software = !update_software(!0, &data);
+            assert_eq!(software, crc32c(&data), "length {}", data.len());
+            assert_eq!(software, bitwise(&data), "length {}", data.len());
+        }
+    }
+
+    #[test]
+    fn incremental_matches_one_shot() {
+        let data = data(1000);
+        for split in [0, 1, 7, 8, 9, 500, 999, 1000] {
+            let (head, tail) = data.split_at(split);
+            assert_eq!(
+                update(crc32c(head), tail),
+                crc32c(&data),
+                "split at {split}"
+            );
+        }
+    }
+}
@@ -0,0 +1,159 @@
+//! Filtering of replayed records for at-least-once consumers.
+//!
+//! After a rebalance or a failed commit the same records are delivered
+//! again. [`Deduplicator`] remembers the keys of recently processed records
+//! in a bounded least-recently-used store, so replays inside that window can
+//! be skipped. Keys are either record positions ([`OffsetKey`]) or any
+//! record id the application extracts itself.
+
+use std::collections::{BTreeMap, HashMap};
+use std::hash::Hash;
+
+use crate::topic::TopicPartition;
+
+/// Identifies a record by where it was read from
+pub type OffsetKey = (TopicPartition, i64);
+
+/// Bounded store of recently seen record keys, evicting the least recently seen
+#[derive(Debug, Clone)]
+pub struct Deduplicator<K = OffsetKey> {
+    capacity: usize,
+    /// Key to the tick it was last seen at
+    seen: HashMap<K, u64>,
+    /// Tick to key, oldest first
+    order: BTreeMap<u64, K>,
+    tick: u64,
+    duplicates: u64,
+}
+
+impl<K: Hash + Eq + Clone> Deduplicator<K> {
+    /// Creates a store remembering up to `capacity` keys
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity: capacity.max(1),
+            seen: HashMap::new(),
+            order: BTreeMap::new(),
+            tick: 0,
+            duplicates: 0,
+        }
+    }
+
+    /// Records `key` and returns whether it is new, i.e. the record should be processed
+    pub fn check(&mut self, key: K) -> bool {
+        self.tick += 1;
+        if let Some(last_seen) = self.seen.get_mut(&key) {
+            self.order.remove(last_seen);
+            *last_seen = self.tick;
+            self.order.insert(self.tick, key);
+            self.duplicates += 1;
+            return false;
+        }
+
+        if self.seen.len() >= self.capacity
+            && let Some((_, oldest)) = self.order.pop_first()
+        {
+            self.seen.remove(&oldest);
+        }
+        self.seen.insert(key.clone(), self.tick);
+        self.order.insert(self.tick, key);
+        true
+    }
+
+    /// Whether `key` is remembered, without refreshing it
+    pub fn contains(&self, key: &K) -> bool {
+        self.seen.contains_key(key)
+    }
+
+    /// Forgets `key`, e.g. when processing its record failed and must be retried
+    pub fn forget(&mut self, key: &K) -> bool {
+        match self.seen.remove(key) {
+            Some(tick) => {
+                self.order.remove(&tick);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Number of keys remembered
+    pub fn len(&self) -> usize {
+        self.seen.len()
+    }
+
+    /// Whether no key is remembered
+    pub fn is_empty(&self) -> bool {
+        self.seen.is_empty()
+    }
+
+    /// Number of duplicates filtered so far
+    pub const fn duplicates(&self) -> u64 {
+        self.duplicates
+    }
+
+    /// Forgets every key
+    pub fn clear(&mut self) {
+        self.seen.clear();
+        self.order.clear();
+    }
+}
+
+impl Deduplicator<OffsetKey> {
+    /// Records a record position and returns whether it is new
+    pub fn check_offset(&mut self, partition: &TopicPartition, offset: i64) -> bool {
+        self.check((partition.clone(), offset))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn duplicates_are_detected() {
+        let mut dedup = Deduplicator::new(10);
+        let tp = TopicPartition::new("t", 0);
+        assert!(dedup.check_offset(&tp, 5));
+        assert!(dedup.check_offset(&TopicPartition::new("t", 1), 5));
+        assert!(!dedup.check_offset(&tp, 5));
+        assert!(!dedup.check_offset(&tp, 5));
+        assert_eq!(dedup.duplicates(), 2);
+        assert_eq!(dedup.len(), 2);
+
+        assert!(dedup.forget(&(tp.clone(), 5)));
+        assert!(!dedup.forget(&(tp.clone(), 5)));
+        assert!(dedup.check_offset(&tp, 5));
+    }
+
+    #[test]
+    fn least_recently_seen_keys_are_evicted_first() {
+        let mut dedup = Deduplicator::new(3);
+        for key in ["a", "b", "c"] {
+            assert!(dedup.check(key));
+        }
+        // Seeing "a" again makes "b" the oldest
+        assert!(!dedup.check("a"));
+        assert!(dedup.check("d"));
+        assert!(!dedup.contains(&"b"));
+        assert!(dedup.contains(&"a") && dedup.contains(&"c") && dedup.contains(&"d"));
+
+        // `contains` does not refresh, so "c" goes next
+        assert!(dedup.check("e"));
+        assert!(!dedup.contains(&"c"));
+        assert_eq!(dedup.len(), 3);
+
+        // An evicted key is new again
+        assert!(dedup.check("b"));
+        assert!(!dedup.contains(&"a"));
+    }
+
+    #[test]
+    fn zero_capacity_still_remembers_the_last_key() {
+        let mut dedup = Deduplicator::new(0);
+        assert!(dedup.check(1));
+        assert!(!dedup.check(1));
+        assert!(dedup.check(2));
+        assert!(dedup.check(1));
+        dedup.clear();
+        assert!(dedup.is_empty());
+    }
+}
@@ -0,0 +1,250 @@
+//! Error type shared by every layer of the client.
+
+use std::fmt;
+use std::io;
+use std::time::Duration;
+
+use crate::net;
+use crate::protocol::error_code::ErrorCode;
+use crate::records::batch::Compression;
+use crate::topic::TopicPartition;
+
+/// Errors produced while talking to a Kafka broker
+///
+/// New variants may be added in minor releases; match on [`KafkaError::kind`]
+/// where a catch-all is not wanted.
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum KafkaError {
+    /// The underlying socket failed
+    Io(io::Error),
+    /// The broker sent something we could not make sense of
+    ProtocolError(String),
+    /// Bytes did not decode as the expected format, or a value could not be encoded
+    Serialization(String),
+    /// A response arrived for a request we never sent, so the frame boundary was lost
+    CorrelationMismatch { expected: i32, received: i32 },
+    /// The client configuration is invalid
+    Config(String),
+    /// The broker does not implement this version of the API
+    UnsupportedVersion { api_key: i16, version: i16 },
+    /// The broker rejected the client's SASL mechanism or credentials
+    Authentication(String),
+    /// The broker answered with an error code
+    Broker(ErrorCode),
+    /// Partitions of a topic still had no leader when the wait for them ended
+    NoLeaderAvailable {
+        topic: String,
+        /// Leaderless partitions; empty if the topic itself was not available yet
+        partitions: Vec<i32>,
+    },
+    /// A fetched batch is compressed with a codec the client cannot decode
+    UnsupportedCodec {
+        codec: Compression,
+        partition: TopicPartition,
+    },
+    /// An operation did not finish in time
+    Timeout {
+        kind: TimeoutKind,
+        /// What was being attempted, e.g. "connecting to 10.0.0.1:9092"
+        context: String,
+        /// The limit that was exceeded, when known
+        limit: Option<Duration>,
+    },
+}
+
+/// Stable classification of a [`KafkaError`] for matching in applications
+///
+/// Variants of [`KafkaError`] carry details and may change as the client
+/// grows; the kind only says how to react, so matching on it keeps working
+/// across releases.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum ErrorKind {
+    /// Transient; retrying the operation, possibly after a reconnect, may succeed
+    Retriable,
+    /// Retrying will not help, e.g. the broker sent data the client cannot parse
+    Fatal,
+    /// The client could not prove its identity
+    Authentication,
+    /// The client is not allowed to perform the operation
+    Authorization,
+    /// A value could not be encoded or decoded
+    Serialization,
+    /// A deadline passed; the operation may or may not have taken effect
+    Timeout,
+    /// The client configuration is invalid
+    Configuration,
+    /// The broker does not support what was asked of it
+    Unsupported,
+}
+
+impl fmt::Display for ErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Retriable => "retriable",
+            Self::Fatal => "fatal",
+            Self::Authentication => "authentication",
+            Self::Authorization => "authorization",
+            Self::Serialization => "serialization",
+            Self::Timeout => "timeout",
+            Self::Configuration => "configuration",
+            Self::Unsupported => "unsupported",
+        })
+    }
+}
+
+/// Which deadline a [`KafkaError::Timeout`] exceeded
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum TimeoutKind {
+    /// Establishing the TCP connection to a broker
+    Connect,
+    /// Sending a request or waiting for its response
+    Request,
+}
+
+impl fmt::Display for TimeoutKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Connect => f.write_str("connect timeout"),
+            Self::Request => f.write_str("request timeout"),
+        }
+    }
+}
+
+impl fmt::Display for KafkaError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Io(e) => write!(f, "I/O error: {e}"),
+            Self::ProtocolError(msg) => write!(f, "protocol error: {msg}"),
+            Self::Serialization(msg) => write!(f, "serialization error: {msg}"),
+            Self::CorrelationMismatch { expected, received } => write!(
+                f,
+                "correlation id mismatch: expected {expected}, received {received}"
+            ),
+            Self::Config(msg) => write!(f, "invalid configuration: {msg}"),
+            Self::UnsupportedVersion { api_key, version } => write!(
+                f,
+                "broker does not support version {version} of API key {api_key}"
+            ),
+            Self::Authentication(msg) => write!(f, "authentication failed: {msg}"),
+            Self::Broker(code) => write!(f, "broker error: {code}"),
+            Self::NoLeaderAvailable { topic, partitions } if partitions.is_empty() => {
+                write!(f, "no leader available for topic {topic}")
+            }
+            Self::NoLeaderAvailable { topic, partitions } => write!(
+                f,
+                "no leader available for partitions {partitions:?} of topic {topic}"
+            ),
+            Self::UnsupportedCodec { codec, partition } => write!(
+                f,
+                "cannot decode {codec:?} compressed records of partition {partition}"
+            ),
+            Self::Timeout {
+                kind,
+                context,
+                limit: Some(limit),
+            } => write!(f, "{kind} after {limit:?} while {context}"),
+            Self::Timeout {
+                kind,
+                context,
+                limit: None,
+            } => write!(f, "{kind} while {context}"),
+        }
+    }
+}
+
+impl std::error::Error for KafkaError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl KafkaError {
+    /// Classifies the error
+    pub const fn kind(&self) -> ErrorKind {
+        match self {
+            Self::Io(_) | Self::CorrelationMismatch { .. } => ErrorKind::Retriable,
+            Self::ProtocolError(_) => ErrorKind::Fatal,
+            Self::Serialization(_) => ErrorKind::Serialization,
+            Self::Config(_) => ErrorKind::Configuration,
+            Self::UnsupportedVersion { .. } | Self::UnsupportedCodec { .. } => {
+                ErrorKind::Unsupported
+            }
+            Self::Authentication(_) => ErrorKind::Authentication,
+            Self::Broker(code) if code.is_retriable() => ErrorKind::Retriable,
+            Self::Broker(code) if code.is_authentication() => ErrorKind::Authentication,
+            Self::Broker(code) if code.is_authorization() => ErrorKind::Authorization,
+            Self::Broker(
+                ErrorCode::UnsupportedVersion
+                | ErrorCode::UnsupportedForMessageFormat
+                | ErrorCode::UnsupportedCompressionType,
+            ) => ErrorKind::Unsupported,
+            Self::Broker(ErrorCode::InvalidConfig) => ErrorKind::Configuration,
+            Self::Broker(_) => ErrorKind::Fatal,
+            Self::NoLeaderAvailable { .. } => ErrorKind::Retriable,
+            Self::Timeout { .. } => ErrorKind::Timeout,
+        }
+    }
+
+    /// Whether retrying the failed operation may succeed
+    pub const fn is_retriable(&self) -> bool {
+        matches!(self.kind(), ErrorKind::Retriable | ErrorKind::Timeout)
+    }
+
+    /// The broker error code, if the broker rejected the request
+    pub const fn error_code(&self) -> Option<ErrorCode> {
+        match self {
+            Self::Broker(code) => Some(*code),
+            _ => None,
+        }
+    }
+
+    /// Turns an I/O error into a [`KafkaError::Timeout`] if it was a socket timeout
+    pub(crate) fn from_io(
+        error: io::Error,
+        kind: TimeoutKind,
+        context: impl FnOnce() -> String,
+        limit: Option<Duration>,
+    ) -> Self {
+        if net::is_timeout(&error) {
+            Self::Timeout {
+                kind,
+                context: context(),
+                limit,
+            }
+        } else {
+            Self::Io(error)
+        }
+    }
+}
+
+impl From<io::Error> for KafkaError {
+    fn from(e: io::Error) -> Self {
+        Self::Io(e)
+    }
+}
+
+/// Result alias used throughout the crate
+pub type Result<T> = std::result::Result<T, KafkaError>;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::protocol::codec::Cursor;
+
+    #[test]
+    fn decode_failures_are_serialization_errors() {
+        let error = Cursor::new(&[0, 1]).read::<i32>().unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::Serialization);
+        assert!(!error.is_retriable());
+        assert_eq!(
+            KafkaError::ProtocolError("lost sync".into()).kind(),
+            ErrorKind::Fatal
+        );
+    }
+}
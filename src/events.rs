@@ -0,0 +1,49 @@
+//! Callbacks notifying applications about connection and cluster events.
+//!
+//! Implement [`ClientEvents`] to alert or log on these events instead of
+//! parsing library output; every method has an empty default so only the
+//! interesting ones need overriding.
+
+use std::net::SocketAddr;
+use std::time::Duration;
+
+#[cfg(doc)]
+use crate::error::ErrorKind;
+use crate::error::KafkaError;
+use crate::protocol::ApiKey;
+use crate::protocol::metadata::MetadataResponse;
+
+/// Receiver of client lifecycle events
+pub trait ClientEvents: Send + Sync {
+    /// A connection to a broker was opened
+    fn connection_established(&self, _broker: SocketAddr) {}
+
+    /// A connection failed and has to be reopened
+    ///
+    /// Raised for I/O errors, including the broker closing the connection,
+    /// request timeouts and responses that broke the framing.
+    fn connection_lost(&self, _broker: Option<SocketAddr>, _error: &KafkaError) {}
+
+    /// Cluster metadata was fetched; `metadata` is the client's cached view
+    /// after merging in the response
+    fn metadata_updated(&self, _metadata: &MetadataResponse) {}
+
+    /// A request failed in a way retrying cannot fix: [`ErrorKind::Fatal`], or
+    /// [`ErrorKind::Serialization`] for a response that did not decode
+    fn fatal_error(&self, _error: &KafkaError) {}
+
+    /// A broker reported that it delayed a response to enforce a quota
+    fn request_throttled(
+        &self,
+        _broker: Option<SocketAddr>,
+        _api_key: ApiKey,
+        _throttle: Duration,
+    ) {
+    }
+}
+
+/// Event receiver that ignores everything
+#[derive(Debug, Default, Clone, Copy)]
+pub struct NoopEvents;
+
+impl ClientEvents for NoopEvents {}
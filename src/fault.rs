@@ -0,0 +1,264 @@
+//! Fault injection for exercising retry, reconnect and resynchronization logic.
+//!
+//! [`FaultyStream`] wraps any transport and misbehaves according to a
+//! [`FaultPlan`]: it can drop the connection after a number of bytes, delay
+//! responses, corrupt or duplicate a chosen response frame. Responses are
+//! handled frame by frame, so faults hit exactly the response they target.
+
+use std::collections::VecDeque;
+use std::io::{self, Read, Write};
+use std::thread;
+use std::time::Duration;
+
+/// Which faults to inject, and when
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct FaultPlan {
+    /// Fail every operation once this many bytes have been sent and received in total
+    pub drop_after_bytes: Option<usize>,
+    /// Delay before each response frame is handed to the client
+    pub response_delay: Option<Duration>,
+    /// Zero-based index of a response frame whose correlation id gets corrupted
+    pub corrupt_response: Option<usize>,
+    /// Zero-based index of a response frame that is delivered twice
+    pub duplicate_response: Option<usize>,
+}
+
+/// A transport that injects the faults of a [`FaultPlan`]
+pub struct FaultyStream<S> {
+    inner: S,
+    plan: FaultPlan,
+    /// Bytes sent and received so far
+    transferred: usize,
+    /// Response frames read from the inner stream so far
+    responses: usize,
+    /// Response bytes ready to be handed to the client
+    pending: VecDeque<u8>,
+}
+
+impl<S> FaultyStream<S> {
+    /// Wraps `inner`, injecting the faults of `plan`
+    pub const fn new(inner: S, plan: FaultPlan) -> Self {
+        Self {
+            inner,
+            plan,
+            transferred: 0,
+            responses: 0,
+            pending: VecDeque::new(),
+        }
+    }
+
+    /// Returns the wrapped transport
+    pub fn into_inner(self) -> S {
+        self.inner
+    }
+
+    /// Fails once the byte budget is spent, otherwise returns how many bytes may still pass
+    fn budget(&self, wanted: usize) -> io::Result<usize> {
+        match self.plan.drop_after_bytes {
+            Some(limit) if self.transferred >= limit => Err(io::Error::new(
+                io::ErrorKind::ConnectionReset,
+                "connection dropped by fault injection",
+            )),
+            Some(limit) => Ok(wanted.min(limit - self.transferred)),
+            None => Ok(wanted),
+        }
+    }
+}
+
+impl<S: Read> FaultyStream<S> {
+    /// Reads the next complete response frame and queues it with its faults applied
+    fn fill(&mut self) -> io::Result<()> {
+        let mut size = [0u8; 4];
+        self.inner.read_exact(&mut size)?;
+        let len = usize::try_from(i32::from_be_bytes(size))
+            .map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "negative frame size"))?;
+        let mut frame = size.to_vec();
+        frame.resize(4 + len, 0);
+        self.inner.read_exact(&mut frame[4..])?;
+
+        let index = self.responses;
+        self.responses += 1;
+
+        if let Some(delay) = self.plan.response_delay {
+            thread::sleep(delay);
+        }
+        if self.plan.corrupt_response == Some(index) {
+            for byte in frame.iter_mut().skip(4).take(4) {
+                *byte = !*byte;
+            }
+        }
+        if self.plan.duplicate_response == Some(index) {
+            self.pending.extend(&frame);
+        }
+        self.pending.extend(frame);
+        Ok(())
+    }
+}
+
+impl<S: Read> Read for FaultyStream<S> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let wanted = self.budget(buf.len())?;
+        if wanted == 0 {
+            return Ok(0);
+        }
+        if self.pending.is_empty() {
+            self.fill()?;
+        }
+
+        let n = self.pending.read(&mut buf[..wanted])?;
+        self.transferred += n;
+        Ok(n)
+    }
+}
+
+impl<S: Write> Write for FaultyStream<S> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let allowed = self.budget(buf.len())?;
+        let n = self.inner.write(&buf[..allowed])?;
+        self.transferred += n;
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.budget(0)?;
+        self.inner.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::SocketAddr;
+    use std::sync::Arc;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use super::*;
+    use crate::client::tests::old_broker;
+    use crate::connection::Connection;
+    use crate::connection::tests::{Echo, reconnecting};
+    use crate::error::KafkaError;
+    use crate::events::ClientEvents;
+    use crate::{ErrorKind, KafkaClient};
+
+    /// Metadata v1, whose responses use header v0
+    const API_KEY: i16 = 3;
+    const API_VERSION: i16 = 1;
+
+    fn faulty(handler: bool, plan: FaultPlan) -> FaultyStream<Echo> {
+        let echo = Echo {
+            handler: handler.then_some(old_broker as _),
+            ..Echo::default()
+        };
+        FaultyStream::new(echo, plan)
+    }
+
+    /// A connection over a faulty echo that reconnects without faults
+    fn connection(plan: FaultPlan) -> Connection<FaultyStream<Echo>> {
+        reconnecting(faulty(false, plan), |_, _| {
+            Ok(faulty(false, FaultPlan::default()))
+        })
+    }
+
+    /// Counts reconnections
+    #[derive(Default)]
+    struct Reconnects(AtomicUsize);
+
+    impl ClientEvents for Reconnects {
+        fn connection_established(&self, _broker: SocketAddr) {
+            self.0.fetch_add(1, Ordering::SeqCst);
+        }
+    }
+
+    /// A client of [`old_broker`] that reconnects without faults
+    fn client(plan: FaultPlan, events: Arc<Reconnects>) -> KafkaClient<FaultyStream<Echo>> {
+        let connection = reconnecting(faulty(true, plan), |_, _| {
+            Ok(faulty(true, FaultPlan::default()))
+        });
+        KafkaClient::from_connection_with_events(connection, events).unwrap()
+    }
+
+    fn request(
+        connection: &mut Connection<FaultyStream<Echo>>,
+        body: &[u8],
+    ) -> Result<Vec<u8>, KafkaError> {
+        let id = connection.send_request(API_KEY, API_VERSION, body)?;
+        connection.receive_response(id)
+    }
+
+    #[test]
+    fn dropped_connection_fails_until_reset() {
+        // The 22-byte request goes through, the 12-byte response is cut after 9
+        let mut connection = connection(FaultPlan {
+            drop_after_bytes: Some(31),
+            ..FaultPlan::default()
+        });
+        let error = request(&mut connection, b"body").unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::Retriable);
+        assert!(matches!(error, KafkaError::Io(_)));
+        assert_eq!(connection.debug_dump().buffered_bytes, 9);
+        assert!(request(&mut connection, b"body").is_err());
+
+        connection.reset().unwrap();
+        assert_eq!(request(&mut connection, b"body").unwrap(), b"body");
+    }
+
+    #[test]
+    fn corrupt_response_desynchronizes_until_reset() {
+        let mut connection = connection(FaultPlan {
+            corrupt_response: Some(1),
+            ..FaultPlan::default()
+        });
+        assert_eq!(request(&mut connection, b"first").unwrap(), b"first");
+        assert!(matches!(
+            request(&mut connection, b"second"),
+            Err(KafkaError::CorrelationMismatch { expected: 1, .. })
+        ));
+        assert!(connection.is_desynchronized());
+
+        connection.reset().unwrap();
+        assert_eq!(request(&mut connection, b"third").unwrap(), b"third");
+    }
+
+    #[test]
+    fn duplicate_response_is_never_taken_for_the_next_one() {
+        let mut connection = connection(FaultPlan {
+            duplicate_response: Some(0),
+            ..FaultPlan::default()
+        });
+        assert_eq!(request(&mut connection, b"first").unwrap(), b"first");
+        assert!(matches!(
+            request(&mut connection, b"second"),
+            Err(KafkaError::CorrelationMismatch {
+                expected: 1,
+                received: 0
+            })
+        ));
+        assert!(connection.is_desynchronized());
+    }
+
+    #[test]
+    fn client_resets_and_resends_after_corrupt_or_duplicate_responses() {
+        // Responses 0 and 1 bootstrap ApiVersions; 2 answers the first Metadata request
+        for plan in [
+            FaultPlan {
+                corrupt_response: Some(2),
+                ..FaultPlan::default()
+            },
+            FaultPlan {
+                duplicate_response: Some(1),
+                ..FaultPlan::default()
+            },
+        ] {
+            let events = Arc::new(Reconnects::default());
+            let mut client = client(plan, events.clone());
+            let metadata = client.fetch_metadata(&["t"]).unwrap();
+            assert_eq!(metadata.topics[0].name.as_deref(), Some("t"));
+            assert_eq!(events.0.load(Ordering::SeqCst), 1);
+        }
+    }
+
+    #[test]
+    fn empty_reads_do_not_block() {
+        let mut stream = faulty(false, FaultPlan::default());
+        assert_eq!(stream.read(&mut []).unwrap(), 0);
+    }
+}
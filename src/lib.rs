@@ -0,0 +1,55 @@
+//! A minimal Kafka client speaking the wire protocol directly over TCP.
+//!
+//! The crate is organised in layers:
+//! - `assignor`: pluggable partition assignment for consumer groups
+//! - `auth`: SASL authentication of broker connections
+//! - `backoff`: retry policies with jittered delays and shared retry budgets
+//! - `chunking`: splitting of oversized payloads into records and back
+//! - `client`: a broker client that negotiates API versions
+//! - `clusters`: registry of named clusters with lazily connected clients
+//! - `config`: client settings addressed by Kafka property names
+//! - `connection`: framing, correlation ids and pipelined requests
+//! - `crc32c`: hardware-accelerated CRC32C checksums with a table fallback
+//! - `dedup`: bounded filtering of replayed records
+//! - `error`: the error type shared by every layer
+//! - `events`: callbacks for connection and cluster lifecycle events
+//! - `fault`: fault injection for tests (behind the `fault-injection` feature)
+//! - `metrics`: per-broker aggregation of quota throttle times
+//! - `net`: socket setup with portable timeouts and keepalive
+//! - `offsets`: committable offset tracking for out-of-order completion
+//! - `protocol`: encoding and decoding of individual protocol messages
+//! - `recording`: dry-run and replay transports built on recorded traffic
+//! - `records`: building, parsing and caching of record batches
+//! - `topic`: topic and partition identifiers
+//! - `worker`: per-partition worker pool with ordered offset tracking
+
+pub mod assignor;
+pub mod auth;
+pub mod backoff;
+pub mod chunking;
+pub mod client;
+pub mod clusters;
+pub mod config;
+pub mod connection;
+pub mod crc32c;
+pub mod dedup;
+pub mod error;
+pub mod events;
+#[cfg(feature = "fault-injection")]
+pub mod fault;
+pub mod metrics;
+pub mod net;
+pub mod offsets;
+pub mod protocol;
+pub mod recording;
+pub mod records;
+pub mod topic;
+pub mod worker;
+
+pub use client::KafkaClient;
+pub use clusters::KafkaClusters;
+pub use config::ClientConfig;
+pub use connection::{BrokerConnection, Connection};
+pub use error::{ErrorKind, KafkaError, Result, TimeoutKind};
+pub use protocol::error_code::ErrorCode;
+pub use topic::TopicPartition;
@@ -0,0 +1,114 @@
+//! Aggregated quota throttling, for seeing which brokers slow the client down.
+//!
+//! Brokers enforce byte-rate and request-rate quotas by delaying responses
+//! and reporting the delay as `throttle_time_ms`. [`ThrottleMetrics`] is a
+//! [`ClientEvents`] receiver that sums those delays per broker and API.
+
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::Duration;
+
+use crate::error::KafkaError;
+use crate::events::{ClientEvents, NoopEvents};
+use crate::protocol::ApiKey;
+use crate::protocol::metadata::MetadataResponse;
+
+/// Throttling observed for one broker and API
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct ThrottleStats {
+    /// Responses that reported a non-zero throttle time
+    pub throttled_responses: u64,
+    /// Sum of all reported throttle times
+    pub total: Duration,
+    /// Longest single throttle time
+    pub max: Duration,
+}
+
+/// Broker a statistic belongs to; `None` for transports without a peer address
+pub type BrokerKey = Option<SocketAddr>;
+
+/// Event receiver aggregating throttle times per broker and API
+pub struct ThrottleMetrics {
+    stats: Mutex<HashMap<(BrokerKey, ApiKey), ThrottleStats>>,
+    /// Receiver every event is passed on to
+    inner: Arc<dyn ClientEvents>,
+}
+
+impl Default for ThrottleMetrics {
+    fn default() -> Self {
+        Self::forwarding(Arc::new(NoopEvents))
+    }
+}
+
+impl ThrottleMetrics {
+    /// Creates empty metrics
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Creates empty metrics that also pass every event on to `inner`
+    pub fn forwarding(inner: Arc<dyn ClientEvents>) -> Self {
+        Self {
+            stats: Mutex::new(HashMap::new()),
+            inner,
+        }
+    }
+
+    /// Throttling per broker and API observed so far
+    pub fn snapshot(&self) -> HashMap<(BrokerKey, ApiKey), ThrottleStats> {
+        self.lock().clone()
+    }
+
+    /// Throttling per broker summed over all APIs
+    pub fn by_broker(&self) -> HashMap<BrokerKey, ThrottleStats> {
+        let mut totals = HashMap::<BrokerKey, ThrottleStats>::new();
+        for (&(broker, _), stats) in self.lock().iter() {
+            let total = totals.entry(broker).or_default();
+            total.throttled_responses += stats.throttled_responses;
+            total.total += stats.total;
+            total.max = total.max.max(stats.max);
+        }
+        totals
+    }
+
+    /// Forgets everything recorded so far
+    pub fn reset(&self) {
+        self.lock().clear();
+    }
+
+    fn lock(&self) -> MutexGuard<'_, HashMap<(BrokerKey, ApiKey), ThrottleStats>> {
+        self.stats
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+impl ClientEvents for ThrottleMetrics {
+    fn connection_established(&self, broker: SocketAddr) {
+        self.inner.connection_established(broker);
+    }
+
+    fn connection_lost(&self, broker: Option<SocketAddr>, error: &KafkaError) {
+        self.inner.connection_lost(broker, error);
+    }
+
+    fn metadata_updated(&self, metadata: &MetadataResponse) {
+        self.inner.metadata_updated(metadata);
+    }
+
+    fn fatal_error(&self, error: &KafkaError) {
+        self.inner.fatal_error(error);
+    }
+
+    fn request_throttled(&self, broker: Option<SocketAddr>, api_key: ApiKey, throttle: Duration) {
+        {
+            let mut stats = self.lock();
+            let stats = stats.entry((broker, api_key)).or_default();
+            stats.throttled_responses += 1;
+            stats.total += throttle;
+            stats.max = stats.max.max(throttle);
+        }
+        self.inner.request_throttled(broker, api_key, throttle);
+    }
+}
@@ -0,0 +1,159 @@
+//! Socket setup shared by every broker connection.
+//!
+//! Keeps platform differences in one place: connects never wait for the OS
+//! default timeout (which can exceed two minutes), keepalive is configured
+//! through `socket2` on every platform, and timeouts are recognised whether
+//! the OS reports them as `WouldBlock` (Unix) or `TimedOut` (Windows).
+//!
+//! When a broker resolves to several addresses they are raced "happy
+//! eyeballs" style (RFC 8305): attempts start a short delay apart,
+//! alternating between IPv6 and IPv4, and the first one to succeed wins, so a
+//! broken IPv6 route costs milliseconds rather than a full connect timeout.
+
+use std::io;
+use std::net::{SocketAddr, TcpStream, ToSocketAddrs};
+use std::sync::mpsc;
+use std::thread;
+use std::time::Duration;
+
+use socket2::{SockRef, TcpKeepalive};
+
+/// Socket-level settings applied to every broker connection
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SocketConfig {
+    /// Upper bound for establishing the TCP connection to one address
+    pub connect_timeout: Duration,
+    /// Upper bound for a single read; `None` blocks indefinitely
+    pub read_timeout: Option<Duration>,
+    /// Upper bound for a single write; `None` blocks indefinitely
+    pub write_timeout: Option<Duration>,
+    /// Idle time before keepalive probes are sent; `None` disables keepalive
+    pub keepalive: Option<Duration>,
+    /// Disables Nagle's algorithm so small requests are sent immediately
+    pub nodelay: bool,
+    /// Head start given to each connection attempt before the next address is tried
+    pub attempt_delay: Duration,
+}
+
+impl Default for SocketConfig {
+    fn default() -> Self {
+        Self {
+            connect_timeout: Duration::from_secs(10),
+            read_timeout: Some(Duration::from_secs(30)),
+            write_timeout: Some(Duration::from_secs(30)),
+            keepalive: Some(Duration::from_secs(60)),
+            nodelay: true,
+            attempt_delay: Duration::from_millis(250),
+        }
+    }
+}
+
+/// Connects to whichever address `addr` resolves to answers first
+pub fn connect(addr: impl ToSocketAddrs, config: &SocketConfig) -> io::Result<TcpStream> {
+    let addresses = interleave_families(addr.to_socket_addrs()?.collect());
+    match addresses.as_slice() {
+        [] => Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "address resolved to nothing",
+        )),
+        [address] => connect_address(*address, config),
+        _ => race(addresses, config),
+    }
+}
+
+/// Starts staggered connection attempts and returns the first one to succeed
+fn race(addresses: Vec<SocketAddr>, config: &SocketConfig) -> io::Result<TcpStream> {
+    let (results, finished) = mpsc::channel();
+    let mut pending = addresses.into_iter();
+    let mut running = 0;
+    let mut last_error = None;
+
+    loop {
+        if let Some(address) = pending.next() {
+            let results = results.clone();
+            let config = config.clone();
+            running += 1;
+            // Losing attempts finish in the background; their streams are dropped on send
+            thread::spawn(move || {
+                let _ = results.send(connect_address(address, &config));
+            });
+        } else if running == 0 {
+            return Err(last_error.expect("every attempt reported a result"));
+        }
+
+        // Give the newest attempt a head start, or wait for anything once all have started
+        let outcome = if pending.len() > 0 {
+            finished.recv_timeout(config.attempt_delay).ok()
+        } else {
+            finished.recv().ok()
+        };
+
+        match outcome {
+            Some(Ok(stream)) => return Ok(stream),
+            Some(Err(e)) => {
+                running -= 1;
+                last_error = Some(e);
+            }
+            None => {}
+        }
+    }
+}
+
+/// Orders addresses so IPv6 and IPv4 alternate, starting with the preferred family
+fn interleave_families(addresses: Vec<SocketAddr>) -> Vec<SocketAddr> {
+    let Some(first) = addresses.first() else {
+        return addresses;
+    };
+    let prefer_v6 = first.is_ipv6();
+    let (mut preferred, mut other): (Vec<_>, Vec<_>) = addresses
+        .into_iter()
+        .partition(|address| address.is_ipv6() == prefer_v6);
+
+    let mut interleaved = Vec::with_capacity(preferred.len() + other.len());
+    preferred.reverse();
+    other.reverse();
+    while let Some(address) = preferred.pop() {
+        interleaved.push(address);
+        interleaved.extend(other.pop());
+    }
+    other.reverse();
+    interleaved.extend(other);
+    interleaved
+}
+
+/// Connects to one resolved address, bounded by the connect timeout
+pub fn connect_address(address: SocketAddr, config: &SocketConfig) -> io::Result<TcpStream> {
+    // `connect_timeout` rejects a zero duration, so clamp it to the smallest useful one
+    let timeout = config.connect_timeout.max(Duration::from_millis(1));
+    let stream = TcpStream::connect_timeout(&address, timeout)?;
+    configure(&stream, config)?;
+    Ok(stream)
+}
+
+/// Applies timeouts, keepalive and nodelay to a connected stream
+pub fn configure(stream: &TcpStream, config: &SocketConfig) -> io::Result<()> {
+    stream.set_nodelay(config.nodelay)?;
+    stream.set_read_timeout(non_zero(config.read_timeout))?;
+    stream.set_write_timeout(non_zero(config.write_timeout))?;
+
+    let socket = SockRef::from(stream);
+    match config.keepalive {
+        // Only the idle time is portable; probe interval and count stay at the OS defaults
+        Some(idle) => socket.set_tcp_keepalive(&TcpKeepalive::new().with_time(idle))?,
+        None => socket.set_keepalive(false)?,
+    }
+    Ok(())
+}
+
+/// Whether an I/O error is a socket timeout, on any platform
+pub fn is_timeout(error: &io::Error) -> bool {
+    matches!(
+        error.kind(),
+        io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut
+    )
+}
+
+/// Treats a zero timeout as "no timeout", which std would otherwise reject
+fn non_zero(timeout: Option<Duration>) -> Option<Duration> {
+    timeout.filter(|timeout| !timeout.is_zero())
+}
@@ -0,0 +1,229 @@
+//! Committable offsets for records acknowledged out of order.
+//!
+//! When records are processed asynchronously they complete in any order,
+//! but only an offset whose predecessors all completed may be committed.
+//! [`OffsetTracker`] records which offsets are outstanding per partition and
+//! computes the highest contiguous committable offset, remembering what was
+//! committed last so a commit loop only sends offsets that moved.
+
+use std::collections::{BTreeSet, HashMap};
+
+use crate::topic::TopicPartition;
+
+/// Offsets of one partition that were started and completed
+#[derive(Debug, Default, Clone)]
+struct Progress {
+    /// Started offsets whose processing has not completed
+    in_flight: BTreeSet<i64>,
+    /// Highest offset whose processing completed
+    completed: Option<i64>,
+    /// Offset last reported as committed
+    committed: Option<i64>,
+}
+
+impl Progress {
+    /// Offset to commit: the oldest unfinished record, or the one after the last completed
+    fn committable(&self) -> Option<i64> {
+        self.in_flight
+            .first()
+            .copied()
+            .or(self.completed.map(|offset| offset + 1))
+    }
+}
+
+/// Per-partition tracking of outstanding offsets
+#[derive(Debug, Default, Clone)]
+pub struct OffsetTracker {
+    partitions: HashMap<TopicPartition, Progress>,
+}
+
+impl OffsetTracker {
+    /// Creates a tracker with no partitions
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Marks an offset as handed out for processing
+    ///
+    /// Starting an offset at or below the highest completed one means the
+    /// consumer rewound, so completions before the rewind no longer count.
+    pub fn start(&mut self, partition: &TopicPartition, offset: i64) {
+        let progress = self.progress(partition);
+        if progress
+            .completed
+            .is_some_and(|completed| offset <= completed)
+        {
+            progress.completed = None;
+        }
+        progress.in_flight.insert(offset);
+    }
+
+    /// Marks an offset as processed
+    pub fn complete(&mut self, partition: &TopicPartition, offset: i64) {
+        let progress = self.progress(partition);
+        progress.in_flight.remove(&offset);
+        progress.completed = progress.completed.max(Some(offset));
+    }
+
+    /// Forgets a started offset that will not complete, e.g. because it was never delivered
+    pub fn abandon(&mut self, partition: &TopicPartition, offset: i64) {
+        if let Some(progress) = self.partitions.get_mut(partition) {
+            progress.in_flight.remove(&offset);
+        }
+    }
+
+    /// Offset to commit for a partition, if any record of it completed or is in flight
+    pub fn committable(&self, partition: &TopicPartition) -> Option<i64> {
+        self.partitions
+            .get(partition)
+            .and_then(Progress::committable)
+    }
+
+    /// Offsets that can be committed for every partition seen so far
+    pub fn committable_offsets(&self) -> HashMap<TopicPartition, i64> {
+        self.partitions
+            .iter()
+            .filter_map(|(partition, progress)| {
+                progress
+                    .committable()
+                    .map(|offset| (partition.clone(), offset))
+            })
+            .collect()
+    }
+
+    /// Committable offsets that moved past what was last marked committed
+    pub fn uncommitted_offsets(&self) -> HashMap<TopicPartition, i64> {
+        self.partitions
+            .iter()
+            .filter_map(|(partition, progress)| {
+                let offset = progress.committable()?;
+                (progress.committed < Some(offset)).then(|| (partition.clone(), offset))
+            })
+            .collect()
+    }
+
+    /// Records that `offset` was committed for a partition
+    pub fn mark_committed(&mut self, partition: &TopicPartition, offset: i64) {
+        let progress = self.progress(partition);
+        progress.committed = progress.committed.max(Some(offset));
+    }
+
+    /// Offsets of a partition started but not yet completed
+    pub fn in_flight(&self, partition: &TopicPartition) -> usize {
+        self.partitions
+            .get(partition)
+            .map_or(0, |progress| progress.in_flight.len())
+    }
+
+    /// Partitions with their in-flight counts
+    pub fn in_flight_counts(&self) -> impl Iterator<Item = (&TopicPartition, usize)> {
+        self.partitions
+            .iter()
+            .map(|(partition, progress)| (partition, progress.in_flight.len()))
+    }
+
+    /// Stops tracking a partition, returning its final committable offset
+    pub fn remove(&mut self, partition: &TopicPartition) -> Option<i64> {
+        self.partitions
+            .remove(partition)
+            .and_then(|progress| progress.committable())
+    }
+
+    fn progress(&mut self, partition: &TopicPartition) -> &mut Progress {
+        if !self.partitions.contains_key(partition) {
+            self.partitions
+                .insert(partition.clone(), Progress::default());
+        }
+        self.partitions
+            .get_mut(partition)
+            .expect("progress was just inserted")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn partition() -> TopicPartition {
+        TopicPartition::new("t", 0)
+    }
+
+    #[test]
+    fn only_contiguous_prefixes_are_committable() {
+        let mut tracker = OffsetTracker::new();
+        let tp = partition();
+        assert_eq!(tracker.committable(&tp), None);
+
+        for offset in 10..13 {
+            tracker.start(&tp, offset);
+        }
+        assert_eq!(tracker.committable(&tp), Some(10));
+        tracker.complete(&tp, 12);
+        tracker.complete(&tp, 11);
+        assert_eq!(tracker.committable(&tp), Some(10));
+        assert_eq!(tracker.in_flight(&tp), 1);
+
+        tracker.complete(&tp, 10);
+        assert_eq!(tracker.committable(&tp), Some(13));
+        assert_eq!(tracker.in_flight(&tp), 0);
+    }
+
+    #[test]
+    fn abandoned_offsets_do_not_hold_back_commits() {
+        let mut tracker = OffsetTracker::new();
+        let tp = partition();
+        tracker.start(&tp, 0);
+        tracker.start(&tp, 1);
+        tracker.complete(&tp, 1);
+        tracker.abandon(&tp, 0);
+        assert_eq!(tracker.committable(&tp), Some(2));
+    }
+
+    #[test]
+    fn uncommitted_offsets_only_report_progress() {
+        let mut tracker = OffsetTracker::new();
+        let tp = partition();
+        tracker.start(&tp, 0);
+        tracker.complete(&tp, 0);
+        assert_eq!(
+            tracker.uncommitted_offsets(),
+            HashMap::from([(tp.clone(), 1)])
+        );
+
+        tracker.mark_committed(&tp, 1);
+        assert!(tracker.uncommitted_offsets().is_empty());
+        assert_eq!(
+            tracker.committable_offsets(),
+            HashMap::from([(tp.clone(), 1)])
+        );
+
+        tracker.start(&tp, 1);
+        tracker.complete(&tp, 1);
+        assert_eq!(
+            tracker.uncommitted_offsets(),
+            HashMap::from([(tp.clone(), 2)])
+        );
+        assert_eq!(tracker.remove(&tp), Some(2));
+        assert!(tracker.committable_offsets().is_empty());
+    }
+
+    #[test]
+    fn rewind_does_not_jump_to_old_completions() {
+        let mut tracker = OffsetTracker::new();
+        let tp = partition();
+        for offset in 0..10 {
+            tracker.start(&tp, offset);
+            tracker.complete(&tp, offset);
+        }
+        assert_eq!(tracker.committable(&tp), Some(10));
+
+        // Seek back to 5 and reprocess from there
+        tracker.start(&tp, 5);
+        tracker.start(&tp, 6);
+        assert_eq!(tracker.committable(&tp), Some(5));
+        tracker.complete(&tp, 5);
+        assert_eq!(tracker.committable(&tp), Some(6));
+        tracker.complete(&tp, 6);
+        assert_eq!(tracker.committable(&tp), Some(7));
+    }
+}
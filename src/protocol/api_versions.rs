@@ -0,0 +1,185 @@
+//! ApiVersions (key 18): the range of versions the broker supports for every API.
+
+use std::collections::BTreeMap;
+
+use super::codec::{Cursor, Encode, TaggedFields, UnsignedVarInt, write_compact_string};
+use super::error_code::ErrorCode;
+use crate::error::Result;
+
+/// ApiVersions versions the client implements
+pub const VERSIONS: VersionRange = VersionRange::new(0, 3);
+
+/// Name reported to brokers in ApiVersions v3+
+///
+/// Brokers only accept letters, digits, `-` and `.`, so this cannot be the
+/// package name with its underscore.
+const CLIENT_SOFTWARE_NAME: &str = "rust-kafka";
+
+/// Version reported to brokers in ApiVersions v3+
+const CLIENT_SOFTWARE_VERSION: &str = env!("CARGO_PKG_VERSION");
+
+/// Inclusive range of versions a broker supports for one API
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct VersionRange {
+    pub min: i16,
+    pub max: i16,
+}
+
+impl VersionRange {
+    /// Creates the range `min..=max`
+    pub const fn new(min: i16, max: i16) -> Self {
+        Self { min, max }
+    }
+
+    /// Whether `version` lies within the range
+    pub const fn contains(&self, version: i16) -> bool {
+        self.min <= version && version <= self.max
+    }
+
+    /// Versions in both ranges, or `None` if they do not overlap
+    pub fn intersect(&self, other: &Self) -> Option<Self> {
+        let range = Self::new(self.min.max(other.min), self.max.min(other.max));
+        (range.min <= range.max).then_some(range)
+    }
+}
+
+/// Feature flags reported in the tagged fields of ApiVersions v3+
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct ClusterFeatures {
+    /// Feature versions the broker is able to support
+    pub supported: BTreeMap<String, VersionRange>,
+    /// Epoch of the finalized features, -1 if the broker reported none
+    pub finalized_epoch: i64,
+    /// Feature version levels enabled cluster-wide
+    pub finalized: BTreeMap<String, VersionRange>,
+}
+
+impl Default for ClusterFeatures {
+    fn default() -> Self {
+        Self {
+            supported: BTreeMap::new(),
+            finalized_epoch: -1,
+            finalized: BTreeMap::new(),
+        }
+    }
+}
+
+impl ClusterFeatures {
+    /// Finalized version level of a feature such as `metadata.version`, if enabled
+    pub fn finalized_level(&self, name: &str) -> Option<i16> {
+        self.finalized.get(name).map(|range| range.max)
+    }
+}
+
+/// Decoded ApiVersions response
+#[derive(Debug, Clone, Default)]
+pub struct ApiVersionsResponse {
+    pub error_code: i16,
+    /// Supported versions keyed by API key, including keys this client does not know
+    pub api_keys: BTreeMap<i16, VersionRange>,
+    pub throttle_time_ms: i32,
+    pub features: ClusterFeatures,
+    /// Tagged fields of v3+ responses the client does not decode
+    pub tagged_fields: TaggedFields,
+}
+
+/// Encodes an ApiVersions request body for the given version
+pub fn encode_request(version: i16) -> Vec<u8> {
+    let mut body = Vec::new();
+    if version >= 3 {
+        write_compact_string(&mut body, CLIENT_SOFTWARE_NAME);
+        write_compact_string(&mut body, CLIENT_SOFTWARE_VERSION);
+        TaggedFields::new().encode(&mut body);
+    }
+    body
+}
+
+/// Decodes an ApiVersions response body sent in reply to a request of `version`
+///
+/// A broker that rejects the requested version answers with
+/// UNSUPPORTED_VERSION in the v0 layout, listing the versions it does support.
+pub fn decode_response(version: i16, body: &[u8]) -> Result<ApiVersionsResponse> {
+    let mut cursor = Cursor::new(body);
+    let error_code: i16 = cursor.read()?;
+    let version = if error_code == ErrorCode::UnsupportedVersion.as_i16() {
+        0
+    } else {
+        version
+    };
+    let flexible = version >= 3;
+
+    let count = if flexible {
+        cursor.read::<UnsignedVarInt>()?.0.checked_sub(1)
+    } else {
+        u32::try_from(cursor.read::<i32>()?).ok()
+    };
+
+    let mut api_keys = BTreeMap::new();
+    for _ in 0..count.unwrap_or(0) {
+        let api_key = cursor.read()?;
+        let min = cursor.read()?;
+        let max = cursor.read()?;
+        if flexible {
+            cursor.read::<TaggedFields>()?;
+        }
+        api_keys.insert(api_key, VersionRange { min, max });
+    }
+
+    let throttle_time_ms = if version >= 1 { cursor.read()? } else { 0 };
+
+    let mut tagged_fields = if flexible {
+        cursor.read()?
+    } else {
+        TaggedFields::new()
+    };
+    let features = take_features(&mut tagged_fields)?;
+
+    Ok(ApiVersionsResponse {
+        error_code,
+        api_keys,
+        throttle_time_ms,
+        features,
+        tagged_fields,
+    })
+}
+
+/// Decodes and removes the feature fields from the body's tagged fields
+fn take_features(tagged_fields: &mut TaggedFields) -> Result<ClusterFeatures> {
+    let mut features = ClusterFeatures::default();
+    if let Some(field) = tagged_fields.remove(0) {
+        features.supported = read_feature_ranges(&mut Cursor::new(&field), false)?;
+    }
+    if let Some(field) = tagged_fields.remove(1) {
+        features.finalized_epoch = Cursor::new(&field).read()?;
+    }
+    if let Some(field) = tagged_fields.remove(2) {
+        features.finalized = read_feature_ranges(&mut Cursor::new(&field), true)?;
+    }
+    Ok(features)
+}
+
+/// Reads a compact array of named version ranges
+///
+/// Supported features list their minimum first, finalized features their
+/// maximum level first.
+fn read_feature_ranges(
+    cursor: &mut Cursor<'_>,
+    max_first: bool,
+) -> Result<BTreeMap<String, VersionRange>> {
+    let mut ranges = BTreeMap::new();
+    for _ in 0..cursor.read::<UnsignedVarInt>()?.0.saturating_sub(1) {
+        let name = cursor.read_compact_string()?;
+        let first = cursor.read()?;
+        let second = cursor.read()?;
+        cursor.read::<TaggedFields>()?;
+        let (min, max) = if max_first {
+            (second, first)
+        } else {
+            (first, second)
+        };
+        ranges.insert(name, VersionRange { min, max });
+    }
+    Ok(ranges)
+}
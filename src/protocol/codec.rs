@@ -0,0 +1,527 @@
+//! Primitive encoding shared by every protocol message.
+//!
+//! Messages are written into a plain `Vec<u8>` through [`Encode`] and read
+//! back through a [`Cursor`] with [`Decode`], so each message module only
+//! describes its field order and never slices bytes by hand.
+
+use std::collections::BTreeMap;
+
+use crate::error::{KafkaError, Result};
+
+/// A value that can be written in its wire representation
+pub trait Encode {
+    /// Appends the value to `buf`
+    fn encode(&self, buf: &mut Vec<u8>);
+}
+
+/// A value that can be read from its wire representation
+pub trait Decode: Sized {
+    /// Reads the value at the cursor's position and advances past it
+    fn decode(cursor: &mut Cursor<'_>) -> Result<Self>;
+}
+
+/// Read position within a received message
+#[derive(Debug, Clone)]
+pub struct Cursor<'a> {
+    buf: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Cursor<'a> {
+    /// Starts reading at the beginning of `buf`
+    pub const fn new(buf: &'a [u8]) -> Self {
+        Self { buf, pos: 0 }
+    }
+
+    /// Bytes consumed so far
+    pub const fn position(&self) -> usize {
+        self.pos
+    }
+
+    /// Bytes left to read
+    pub const fn remaining(&self) -> usize {
+        self.buf.len() - self.pos
+    }
+
+    /// Whether every byte has been read
+    pub const fn is_empty(&self) -> bool {
+        self.remaining() == 0
+    }
+
+    /// Reads a value of any decodable type
+    pub fn read<T: Decode>(&mut self) -> Result<T> {
+        T::decode(self)
+    }
+
+    /// Takes the next `len` bytes
+    pub fn take(&mut self, len: usize) -> Result<&'a [u8]> {
+        let bytes = self
+            .buf
+            .get(self.pos..self.pos.saturating_add(len))
+            .ok_or_else(|| {
+                KafkaError::Serialization(format!(
+                    "message is truncated: needed {len} bytes at offset {}, {} left",
+                    self.pos,
+                    self.remaining()
+                ))
+            })?;
+        self.pos += len;
+        Ok(bytes)
+    }
+
+    /// Takes the next `N` bytes as an array
+    pub fn take_array<const N: usize>(&mut self) -> Result<[u8; N]> {
+        let bytes = self.take(N)?;
+        Ok(bytes.try_into().expect("took exactly N bytes"))
+    }
+}
+
+/// String and byte sequence encodings
+///
+/// Classic versions prefix strings with an `i16` and bytes with an `i32`
+/// length, -1 meaning null. Flexible versions use compact encodings: an
+/// unsigned varint holding the length plus one, with 0 meaning null.
+impl<'a> Cursor<'a> {
+    /// Reads a STRING
+    pub fn read_string(&mut self) -> Result<String> {
+        self.read_nullable_string()?
+            .ok_or_else(|| KafkaError::Serialization("unexpected null string".into()))
+    }
+
+    /// Reads a NULLABLE_STRING
+    pub fn read_nullable_string(&mut self) -> Result<Option<String>> {
+        let len = self.read::<i16>()?;
+        self.read_utf8(usize::try_from(len).ok())
+    }
+
+    /// Reads a COMPACT_STRING
+    pub fn read_compact_string(&mut self) -> Result<String> {
+        self.read_compact_nullable_string()?
+            .ok_or_else(|| KafkaError::Serialization("unexpected null compact string".into()))
+    }
+
+    /// Reads a COMPACT_NULLABLE_STRING
+    pub fn read_compact_nullable_string(&mut self) -> Result<Option<String>> {
+        let len = self.read_compact_len()?;
+        self.read_utf8(len)
+    }
+
+    /// Reads BYTES
+    pub fn read_bytes(&mut self) -> Result<&'a [u8]> {
+        self.read_nullable_bytes()?
+            .ok_or_else(|| KafkaError::Serialization("unexpected null bytes".into()))
+    }
+
+    /// Reads NULLABLE_BYTES
+    pub fn read_nullable_bytes(&mut self) -> Result<Option<&'a [u8]>> {
+        let len = self.read::<i32>()?;
+        usize::try_from(len)
+            .ok()
+            .map(|len| self.take(len))
+            .transpose()
+    }
+
+    /// Reads COMPACT_BYTES
+    pub fn read_compact_bytes(&mut self) -> Result<&'a [u8]> {
+        self.read_compact_nullable_bytes()?
+            .ok_or_else(|| KafkaError::Serialization("unexpected null compact bytes".into()))
+    }
+
+    /// Reads COMPACT_NULLABLE_BYTES
+    pub fn read_compact_nullable_bytes(&mut self) -> Result<Option<&'a [u8]>> {
+        let len = self.read_compact_len()?;
+        len.map(|len| self.take(len)).transpose()
+    }
+
+    /// Reads the length of an ARRAY, or of a COMPACT_ARRAY when `flexible`; `None` means null
+    pub fn read_array_len(&mut self, flexible: bool) -> Result<Option<usize>> {
+        if flexible {
+            self.read_compact_len()
+        } else {
+            Ok(usize::try_from(self.read::<i32>()?).ok())
+        }
+    }
+
+    /// Reads a compact length, `None` standing for null
+    fn read_compact_len(&mut self) -> Result<Option<usize>> {
+        let len = self.read::<UnsignedVarInt>()?.0;
+        Ok(len.checked_sub(1).map(|len| len as usize))
+    }
+
+    fn read_utf8(&mut self, len: Option<usize>) -> Result<Option<String>> {
+        let Some(len) = len else {
+            return Ok(None);
+        };
+        let bytes = self.take(len)?;
+        String::from_utf8(bytes.to_vec())
+            .map(Some)
+            .map_err(|_| KafkaError::Serialization("string is not valid UTF-8".into()))
+    }
+}
+
+/// Writes a STRING; strings longer than `i16::MAX` bytes are a caller bug
+pub fn write_string(buf: &mut Vec<u8>, value: &str) {
+    write_nullable_string(buf, Some(value));
+}
+
+/// Writes a NULLABLE_STRING
+pub fn write_nullable_string(buf: &mut Vec<u8>, value: Option<&str>) {
+    match value {
+        Some(value) => {
+            (value.len() as i16).encode(buf);
+            buf.extend_from_slice(value.as_bytes());
+        }
+        None => (-1i16).encode(buf),
+    }
+}
+
+/// Writes a COMPACT_STRING
+pub fn write_compact_string(buf: &mut Vec<u8>, value: &str) {
+    write_compact_nullable_bytes(buf, Some(value.as_bytes()));
+}
+
+/// Writes a COMPACT_NULLABLE_STRING
+pub fn write_compact_nullable_string(buf: &mut Vec<u8>, value: Option<&str>) {
+    write_compact_nullable_bytes(buf, value.map(str::as_bytes));
+}
+
+/// Writes BYTES
+pub fn write_bytes(buf: &mut Vec<u8>, value: &[u8]) {
+    write_nullable_bytes(buf, Some(value));
+}
+
+/// Writes NULLABLE_BYTES
+pub fn write_nullable_bytes(buf: &mut Vec<u8>, value: Option<&[u8]>) {
+    match value {
+        Some(value) => {
+            (value.len() as i32).encode(buf);
+            buf.extend_from_slice(value);
+        }
+        None => (-1i32).encode(buf),
+    }
+}
+
+/// Writes COMPACT_BYTES
+pub fn write_compact_bytes(buf: &mut Vec<u8>, value: &[u8]) {
+    write_compact_nullable_bytes(buf, Some(value));
+}
+
+/// Writes COMPACT_NULLABLE_BYTES
+pub fn write_compact_nullable_bytes(buf: &mut Vec<u8>, value: Option<&[u8]>) {
+    match value {
+        Some(value) => {
+            UnsignedVarInt(value.len() as u32 + 1).encode(buf);
+            buf.extend_from_slice(value);
+        }
+        None => UnsignedVarInt(0).encode(buf),
+    }
+}
+
+/// Writes the length of an ARRAY, or of a COMPACT_ARRAY when `flexible`; `None` means null
+pub fn write_array_len(buf: &mut Vec<u8>, len: Option<usize>, flexible: bool) {
+    match (len, flexible) {
+        (Some(len), true) => UnsignedVarInt(len as u32 + 1).encode(buf),
+        (None, true) => UnsignedVarInt(0).encode(buf),
+        (Some(len), false) => (len as i32).encode(buf),
+        (None, false) => (-1i32).encode(buf),
+    }
+}
+
+macro_rules! fixed_width {
+    ($($ty:ty),*) => {$(
+        impl Encode for $ty {
+            fn encode(&self, buf: &mut Vec<u8>) {
+                buf.extend_from_slice(&self.to_be_bytes());
+            }
+        }
+
+        impl Decode for $ty {
+            fn decode(cursor: &mut Cursor<'_>) -> Result<Self> {
+                cursor.take_array().map(<$ty>::from_be_bytes)
+            }
+        }
+    )*};
+}
+
+fixed_width!(i8, i16, i32, i64, u16, u32, f64);
+
+impl Encode for bool {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        buf.push(u8::from(*self));
+    }
+}
+
+impl Decode for bool {
+    fn decode(cursor: &mut Cursor<'_>) -> Result<Self> {
+        Ok(cursor.take_array::<1>()?[0] != 0)
+    }
+}
+
+/// UNSIGNED_VARINT: 7 bits per byte, least significant group first
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct UnsignedVarInt(pub u32);
+
+/// VARINT: a zigzag-encoded `i32` written as an unsigned varint
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct VarInt(pub i32);
+
+/// VARLONG: a zigzag-encoded `i64` written as an unsigned varint
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct VarLong(pub i64);
+
+impl Encode for UnsignedVarInt {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        write_varint(buf, u64::from(self.0));
+    }
+}
+
+impl Decode for UnsignedVarInt {
+    fn decode(cursor: &mut Cursor<'_>) -> Result<Self> {
+        let value = read_varint(cursor, 5)?;
+        u32::try_from(value)
+            .map(Self)
+            .map_err(|_| KafkaError::Serialization("unsigned varint overflows 32 bits".into()))
+    }
+}
+
+impl Encode for VarInt {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        let zigzag = (self.0 << 1) ^ (self.0 >> 31);
+        write_varint(buf, u64::from(zigzag as u32));
+    }
+}
+
+impl Decode for VarInt {
+    fn decode(cursor: &mut Cursor<'_>) -> Result<Self> {
+        let zigzag = UnsignedVarInt::decode(cursor)?.0;
+        Ok(Self((zigzag >> 1) as i32 ^ -((zigzag & 1) as i32)))
+    }
+}
+
+impl Encode for VarLong {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        let zigzag = (self.0 << 1) ^ (self.0 >> 63);
+        write_varint(buf, zigzag as u64);
+    }
+}
+
+impl Decode for VarLong {
+    fn decode(cursor: &mut Cursor<'_>) -> Result<Self> {
+        let zigzag = read_varint(cursor, 10)?;
+        Ok(Self((zigzag >> 1) as i64 ^ -((zigzag & 1) as i64)))
+    }
+}
+
+fn write_varint(buf: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        buf.push((value as u8) | 0x80);
+        value >>= 7;
+    }
+    buf.push(value as u8);
+}
+
+/// Reads a varint of at most `max_len` bytes
+fn read_varint(cursor: &mut Cursor<'_>, max_len: usize) -> Result<u64> {
+    let mut value = 0u64;
+    for index in 0..max_len {
+        let byte = cursor.take_array::<1>()?[0];
+        value |= u64::from(byte & 0x7f) << (7 * index);
+        if byte & 0x80 == 0 {
+            return Ok(value);
+        }
+    }
+    Err(KafkaError::Serialization(format!(
+        "varint is longer than {max_len} bytes"
+    )))
+}
+
+/// Tagged fields of a flexible-version message, keyed by tag
+///
+/// Every flexible struct ends with such a section. Known tags are decoded
+/// by the message that owns them; unknown ones are kept as raw bytes so
+/// newer brokers can add fields without breaking the client.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TaggedFields(BTreeMap<u32, Vec<u8>>);
+
+impl TaggedFields {
+    /// Creates an empty section
+    pub const fn new() -> Self {
+        Self(BTreeMap::new())
+    }
+
+    /// Raw data of a tag
+    pub fn get(&self, tag: u32) -> Option<&[u8]> {
+        self.0.get(&tag).map(Vec::as_slice)
+    }
+
+    /// Sets the raw data of a tag, returning what it replaced
+    pub fn insert(&mut self, tag: u32, data: Vec<u8>) -> Option<Vec<u8>> {
+        self.0.insert(tag, data)
+    }
+
+    /// Sets a tag to the encoding of `value`
+    pub fn insert_value(&mut self, tag: u32, value: &impl Encode) -> Option<Vec<u8>> {
+        let mut data = Vec::new();
+        value.encode(&mut data);
+        self.insert(tag, data)
+    }
+
+    /// Removes a tag, returning its raw data
+    pub fn remove(&mut self, tag: u32) -> Option<Vec<u8>> {
+        self.0.remove(&tag)
+    }
+
+    /// Number of tags present
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    /// Whether no tag is present
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// Tags with their raw data, in ascending tag order
+    pub fn iter(&self) -> impl Iterator<Item = (u32, &[u8])> {
+        self.0.iter().map(|(&tag, data)| (tag, data.as_slice()))
+    }
+}
+
+impl Encode for TaggedFields {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        UnsignedVarInt(self.0.len() as u32).encode(buf);
+        for (&tag, data) in &self.0 {
+            UnsignedVarInt(tag).encode(buf);
+            UnsignedVarInt(data.len() as u32).encode(buf);
+            buf.extend_from_slice(data);
+        }
+    }
+}
+
+impl Decode for TaggedFields {
+    fn decode(cursor: &mut Cursor<'_>) -> Result<Self> {
+        let mut fields = BTreeMap::new();
+        let mut previous = None;
+        for _ in 0..cursor.read::<UnsignedVarInt>()?.0 {
+            let tag = cursor.read::<UnsignedVarInt>()?.0;
+            if previous.is_some_and(|previous| tag <= previous) {
+                return Err(KafkaError::Serialization(format!(
+                    "tagged field {tag} is out of order"
+                )));
+            }
+            previous = Some(tag);
+
+            let size = cursor.read::<UnsignedVarInt>()?.0 as usize;
+            fields.insert(tag, cursor.take(size)?.to_vec());
+        }
+        Ok(Self(fields))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn string_round_trip() {
+        let mut buf = Vec::new();
+        write_string(&mut buf, "kafka");
+        assert_eq!(buf, b"\x00\x05kafka");
+        let mut cursor = Cursor::new(&buf);
+        assert_eq!(cursor.read_string().unwrap(), "kafka");
+        assert!(cursor.is_empty());
+    }
+
+    #[test]
+    fn nullable_string_round_trip() {
+        let mut buf = Vec::new();
+        write_nullable_string(&mut buf, Some("é"));
+        write_nullable_string(&mut buf, None);
+        write_nullable_string(&mut buf, Some(""));
+        assert_eq!(buf, b"\x00\x02\xc3\xa9\xff\xff\x00\x00");
+
+        let mut cursor = Cursor::new(&buf);
+        assert_eq!(cursor.read_nullable_string().unwrap().as_deref(), Some("é"));
+        assert_eq!(cursor.read_nullable_string().unwrap(), None);
+        assert_eq!(cursor.read_nullable_string().unwrap().as_deref(), Some(""));
+        assert!(cursor.is_empty());
+    }
+
+    #[test]
+    fn compact_string_round_trip() {
+        let mut buf = Vec::new();
+        write_compact_string(&mut buf, "kafka");
+        write_compact_string(&mut buf, "");
+        assert_eq!(buf, b"\x06kafka\x01");
+
+        let mut cursor = Cursor::new(&buf);
+        assert_eq!(cursor.read_compact_string().unwrap(), "kafka");
+        assert_eq!(cursor.read_compact_string().unwrap(), "");
+        assert!(cursor.is_empty());
+    }
+
+    #[test]
+    fn compact_nullable_bytes_round_trip() {
+        let long = vec![7u8; 200];
+        let mut buf = Vec::new();
+        write_compact_nullable_bytes(&mut buf, Some(&[1, 2, 3]));
+        write_compact_nullable_bytes(&mut buf, None);
+        write_compact_nullable_bytes(&mut buf, Some(&long));
+        assert_eq!(&buf[..6], b"\x04\x01\x02\x03\x00\xc9");
+
+        let mut cursor = Cursor::new(&buf);
+        assert_eq!(
+            cursor.read_compact_nullable_bytes().unwrap(),
+            Some(&[1, 2, 3][..])
+        );
+        assert_eq!(cursor.read_compact_nullable_bytes().unwrap(), None);
+        assert_eq!(
+            cursor.read_compact_nullable_bytes().unwrap(),
+            Some(&long[..])
+        );
+        assert!(cursor.is_empty());
+    }
+
+    #[test]
+    fn null_encodings() {
+        let mut buf = Vec::new();
+        write_nullable_bytes(&mut buf, None);
+        assert_eq!(buf, (-1i32).to_be_bytes());
+        assert_eq!(Cursor::new(&buf).read_nullable_bytes().unwrap(), None);
+
+        let mut buf = Vec::new();
+        write_compact_nullable_string(&mut buf, None);
+        assert_eq!(buf, [0]);
+        assert_eq!(
+            Cursor::new(&buf).read_compact_nullable_string().unwrap(),
+            None
+        );
+
+        assert!(Cursor::new(&[0xff, 0xff]).read_string().is_err());
+        assert!(Cursor::new(&[0]).read_compact_string().is_err());
+        assert!(Cursor::new(&[0]).read_compact_bytes().is_err());
+    }
+
+    #[test]
+    fn truncated_input_is_an_error() {
+        // Declared lengths longer than what follows
+        assert!(Cursor::new(b"\x00\x05kaf").read_string().is_err());
+        assert!(Cursor::new(b"\x06kaf").read_compact_string().is_err());
+        assert!(
+            Cursor::new(b"\x00\x00\x00\x04ab")
+                .read_nullable_bytes()
+                .is_err()
+        );
+        assert!(Cursor::new(b"\x04a").read_compact_nullable_bytes().is_err());
+        // Length prefixes cut short
+        assert!(Cursor::new(b"\x00").read_nullable_string().is_err());
+        assert!(Cursor::new(b"\x00\x00").read_bytes().is_err());
+        assert!(Cursor::new(b"\x80").read_compact_string().is_err());
+        assert!(Cursor::new(b"").read_compact_nullable_bytes().is_err());
+    }
+
+    #[test]
+    fn invalid_utf8_is_an_error() {
+        assert!(Cursor::new(b"\x00\x01\xff").read_string().is_err());
+    }
+}
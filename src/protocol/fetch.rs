@@ -0,0 +1,296 @@
+//! Fetch (key 1): reading record batches from partition logs.
+
+use std::collections::BTreeMap;
+
+use super::ApiKey;
+use super::api_versions::VersionRange;
+use super::codec::{
+    Cursor, Encode, TaggedFields, write_array_len, write_compact_string, write_string,
+};
+use super::error_code::ErrorCode;
+use crate::error::Result;
+use crate::records::batch::decode_sized_batches;
+use crate::records::{RecordBatch, decode_batches};
+use crate::topic::TopicPartition;
+
+/// Fetch versions the client implements; v4 is the first returning RecordBatch v2
+/// metadata, and v13 switches to topic ids
+pub const VERSIONS: VersionRange = VersionRange::new(4, 12);
+
+/// Which records a fetch may return
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[repr(i8)]
+pub enum IsolationLevel {
+    /// Everything up to the high watermark
+    #[default]
+    ReadUncommitted = 0,
+    /// Only records of committed transactions, up to the last stable offset
+    ReadCommitted = 1,
+}
+
+/// Where to read one partition from
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FetchPosition {
+    pub fetch_offset: i64,
+    /// Bytes to return at most for this partition
+    pub partition_max_bytes: i32,
+}
+
+/// A Fetch request outside of any fetch session
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FetchRequest {
+    /// How long the broker may wait for `min_bytes` to accumulate
+    pub max_wait_ms: i32,
+    pub min_bytes: i32,
+    /// Bytes to return at most across all partitions
+    pub max_bytes: i32,
+    pub isolation_level: IsolationLevel,
+    pub partitions: BTreeMap<TopicPartition, FetchPosition>,
+}
+
+impl Default for FetchRequest {
+    fn default() -> Self {
+        Self {
+            max_wait_ms: 500,
+            min_bytes: 1,
+            max_bytes: 50 * 1024 * 1024,
+            isolation_level: IsolationLevel::default(),
+            partitions: BTreeMap::new(),
+        }
+    }
+}
+
+/// A transaction aborted within the returned records
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AbortedTransaction {
+    pub producer_id: i64,
+    pub first_offset: i64,
+}
+
+/// Records and offsets returned for one partition
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PartitionFetchResponse {
+    pub error_code: i16,
+    pub high_watermark: i64,
+    /// Last offset below which all transactions are decided, -1 if unknown
+    pub last_stable_offset: i64,
+    /// Start offset of the partition log (v5+), -1 if unknown
+    pub log_start_offset: i64,
+    pub aborted_transactions: Vec<AbortedTransaction>,
+    /// Replica to fetch from instead of the leader (v11+), -1 for none
+    pub preferred_read_replica: i32,
+    /// Raw record set; the last batch may be truncated
+    pub records: Vec<u8>,
+}
+
+impl PartitionFetchResponse {
+    /// The partition's error, if no records could be returned
+    pub const fn error(&self) -> Option<ErrorCode> {
+        ErrorCode::from_i16(self.error_code)
+    }
+
+    /// Decodes the complete batches in the record set
+    pub fn batches(&self) -> Result<Vec<RecordBatch>> {
+        decode_batches(&self.records)
+    }
+
+    /// Decodes the complete batches in the record set with their encoded sizes
+    pub(crate) fn sized_batches(&self) -> Result<Vec<(RecordBatch, usize)>> {
+        decode_sized_batches(&self.records)
+    }
+}
+
+/// Decoded Fetch response
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct FetchResponse {
+    pub throttle_time_ms: i32,
+    /// Top-level error (v7+), such as a fetch session error
+    pub error_code: i16,
+    pub session_id: i32,
+    pub partitions: BTreeMap<TopicPartition, PartitionFetchResponse>,
+}
+
+/// Encodes a Fetch request body for the given version
+pub fn encode_request(version: i16, request: &FetchRequest) -> Vec<u8> {
+    let flexible = ApiKey::Fetch.is_flexible(version);
+    let mut body = Vec::new();
+
+    (-1i32).encode(&mut body); // Replica id: a consumer
+    request.max_wait_ms.encode(&mut body);
+    request.min_bytes.encode(&mut body);
+    request.max_bytes.encode(&mut body);
+    (request.isolation_level as i8).encode(&mut body);
+    if version >= 7 {
+        0i32.encode(&mut body); // Session id: no session
+        (-1i32).encode(&mut body); // Session epoch: full fetch without creating a session
+    }
+
+    let mut topics: BTreeMap<&str, Vec<(i32, FetchPosition)>> = BTreeMap::new();
+    for (partition, &position) in &request.partitions {
+        topics
+            .entry(&partition.topic)
+            .or_default()
+            .push((partition.partition, position));
+    }
+
+    write_array_len(&mut body, Some(topics.len()), flexible);
+    for (topic, partitions) in topics {
+        if flexible {
+            write_compact_string(&mut body, topic);
+        } else {
+            write_string(&mut body, topic);
+        }
+        write_array_len(&mut body, Some(partitions.len()), flexible);
+        for (partition, position) in partitions {
+            partition.encode(&mut body);
+            if version >= 9 {
+                (-1i32).encode(&mut body); // Current leader epoch: unknown
+            }
+            position.fetch_offset.encode(&mut body);
+            if version >= 12 {
+                (-1i32).encode(&mut body); // Last fetched epoch: unknown
+            }
+            if version >= 5 {
+                (-1i64).encode(&mut body); // Log start offset, only used by followers
+            }
+            position.partition_max_bytes.encode(&mut body);
+            if flexible {
+                TaggedFields::new().encode(&mut body);
+            }
+        }
+        if flexible {
+            TaggedFields::new().encode(&mut body);
+        }
+    }
+
+    if version >= 7 {
+        write_array_len(&mut body, Some(0), flexible); // Forgotten topics
+    }
+    if version >= 11 {
+        // Rack id: none, so the leader never redirects to a closer replica
+        if flexible {
+            write_compact_string(&mut body, "");
+        } else {
+            write_string(&mut body, "");
+        }
+    }
+    if flexible {
+        TaggedFields::new().encode(&mut body);
+    }
+    body
+}
+
+/// Decodes a Fetch response body sent in reply to a request of `version`
+pub fn decode_response(version: i16, body: &[u8]) -> Result<FetchResponse> {
+    let flexible = ApiKey::Fetch.is_flexible(version);
+    let mut cursor = Cursor::new(body);
+
+    let throttle_time_ms = cursor.read()?;
+    let (error_code, session_id) = if version >= 7 {
+        (cursor.read()?, cursor.read()?)
+    } else {
+        (0, 0)
+    };
+
+    let mut partitions = BTreeMap::new();
+    for _ in 0..cursor.read_array_len(flexible)?.unwrap_or(0) {
+        let topic = if flexible {
+            cursor.read_compact_string()?
+        } else {
+            cursor.read_string()?
+        };
+        for _ in 0..cursor.read_array_len(flexible)?.unwrap_or(0) {
+            let partition = cursor.read()?;
+            let response = decode_partition(&mut cursor, version, flexible)?;
+            partitions.insert(TopicPartition::new(topic.clone(), partition), response);
+        }
+        if flexible {
+            cursor.read::<TaggedFields>()?;
+        }
+    }
+    if flexible {
+        cursor.read::<TaggedFields>()?;
+    }
+
+    ApiKey::Fetch.check_version(
+        version,
+        partitions
+            .values()
+            .map(|partition| partition.error_code)
+            .chain([error_code]),
+    )?;
+
+    Ok(FetchResponse {
+        throttle_time_ms,
+        error_code,
+        session_id,
+        partitions,
+    })
+}
+
+fn decode_partition(
+    cursor: &mut Cursor<'_>,
+    version: i16,
+    flexible: bool,
+) -> Result<PartitionFetchResponse> {
+    let error_code = cursor.read()?;
+    let high_watermark = cursor.read()?;
+    let last_stable_offset = cursor.read()?;
+    let log_start_offset = if version >= 5 { cursor.read()? } else { -1 };
+
+    let mut aborted_transactions = Vec::new();
+    for _ in 0..cursor.read_array_len(flexible)?.unwrap_or(0) {
+        aborted_transactions.push(AbortedTransaction {
+            producer_id: cursor.read()?,
+            first_offset: cursor.read()?,
+        });
+        if flexible {
+            cursor.read::<TaggedFields>()?;
+        }
+    }
+
+    let preferred_read_replica = if version >= 11 { cursor.read()? } else { -1 };
+    let records = if flexible {
+        cursor.read_compact_nullable_bytes()?
+    } else {
+        cursor.read_nullable_bytes()?
+    };
+    // v12 tags carry diverging epochs and leader hints, which only matter to followers
+    if flexible {
+        cursor.read::<TaggedFields>()?;
+    }
+
+    Ok(PartitionFetchResponse {
+        error_code,
+        high_watermark,
+        last_stable_offset,
+        log_start_offset,
+        aborted_transactions,
+        preferred_read_replica,
+        records: records.unwrap_or_default().to_vec(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::KafkaError;
+
+    #[test]
+    fn unsupported_version_is_reported() {
+        let mut body = 0i32.to_be_bytes().to_vec(); // throttle_time_ms
+        body.extend(ErrorCode::UnsupportedVersion.as_i16().to_be_bytes());
+        body.extend(0i32.to_be_bytes()); // session_id
+        body.extend(0i32.to_be_bytes()); // responses
+
+        assert!(matches!(
+            decode_response(7, &body),
+            Err(KafkaError::UnsupportedVersion {
+                api_key: 1,
+                version: 7
+            })
+        ));
+        body[4..6].copy_from_slice(&0i16.to_be_bytes());
+        assert!(decode_response(7, &body).is_ok());
+    }
+}
@@ -0,0 +1,90 @@
+//! FindCoordinator (key 10): the broker coordinating a consumer group or transactional id.
+
+use super::ApiKey;
+use super::api_versions::VersionRange;
+use super::codec::{Cursor, Encode, TaggedFields, write_compact_string, write_string};
+use crate::error::Result;
+
+/// FindCoordinator versions the client implements; v4 batches keys and is not needed yet
+pub const VERSIONS: VersionRange = VersionRange::new(0, 3);
+
+/// What kind of key a coordinator is looked up for
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+#[repr(i8)]
+pub enum CoordinatorType {
+    /// A consumer group id
+    Group = 0,
+    /// A transactional id; requires v1+
+    Transaction = 1,
+}
+
+/// Address of a coordinating broker
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Coordinator {
+    pub node_id: i32,
+    pub host: String,
+    pub port: i32,
+}
+
+/// Decoded FindCoordinator response
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FindCoordinatorResponse {
+    pub throttle_time_ms: i32,
+    pub error_code: i16,
+    pub error_message: Option<String>,
+    pub coordinator: Coordinator,
+}
+
+/// Encodes a FindCoordinator request body for the given version
+pub fn encode_request(version: i16, key_type: CoordinatorType, key: &str) -> Vec<u8> {
+    let mut body = Vec::with_capacity(4 + key.len());
+    if ApiKey::FindCoordinator.is_flexible(version) {
+        write_compact_string(&mut body, key);
+    } else {
+        write_string(&mut body, key);
+    }
+    if version >= 1 {
+        (key_type as i8).encode(&mut body);
+    }
+    if ApiKey::FindCoordinator.is_flexible(version) {
+        TaggedFields::new().encode(&mut body);
+    }
+    body
+}
+
+/// Decodes a FindCoordinator response body sent in reply to a request of `version`
+///
+/// UNSUPPORTED_VERSION is reported as [`KafkaError::UnsupportedVersion`] so
+/// that [`send_versioned`](crate::KafkaClient::send_versioned) retries lower.
+pub fn decode_response(version: i16, body: &[u8]) -> Result<FindCoordinatorResponse> {
+    let flexible = ApiKey::FindCoordinator.is_flexible(version);
+    let mut cursor = Cursor::new(body);
+
+    let throttle_time_ms = if version >= 1 { cursor.read()? } else { 0 };
+    let error_code = cursor.read()?;
+    ApiKey::FindCoordinator.check_version(version, [error_code])?;
+
+    let error_message = match (version, flexible) {
+        (0, _) => None,
+        (_, true) => cursor.read_compact_nullable_string()?,
+        (_, false) => cursor.read_nullable_string()?,
+    };
+    let node_id = cursor.read()?;
+    let host = if flexible {
+        cursor.read_compact_string()?
+    } else {
+        cursor.read_string()?
+    };
+    let port = cursor.read()?;
+
+    Ok(FindCoordinatorResponse {
+        throttle_time_ms,
+        error_code,
+        error_message,
+        coordinator: Coordinator {
+            node_id,
+            host,
+            port,
+        },
+    })
+}
@@ -0,0 +1,160 @@
+//! Request and response headers, whose layout depends on the API version.
+//!
+//! Flexible API versions use request header v2 and response header v1, which
+//! append a tagged-field section to v1 and v0 respectively. ApiVersions
+//! responses always use header v0 so that a broker can answer clients whose
+//! flexible versions it does not understand.
+
+use super::ApiKey;
+use super::codec::{Cursor, Encode, TaggedFields, write_nullable_string};
+use crate::error::Result;
+
+/// Header written in front of every request body
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RequestHeader {
+    pub api_key: i16,
+    pub api_version: i16,
+    pub correlation_id: i32,
+    pub client_id: Option<String>,
+    /// Sent only in header v2
+    pub tagged_fields: TaggedFields,
+}
+
+impl RequestHeader {
+    /// Creates a header for a request of `api_key` at `api_version`
+    pub fn new(api_key: i16, api_version: i16, correlation_id: i32, client_id: &str) -> Self {
+        Self {
+            api_key,
+            api_version,
+            correlation_id,
+            client_id: Some(client_id.to_string()),
+            tagged_fields: TaggedFields::new(),
+        }
+    }
+
+    /// Header version the request is written with
+    pub fn version(&self) -> i16 {
+        ApiKey::from_i16(self.api_key).map_or(1, |key| key.request_header_version(self.api_version))
+    }
+}
+
+impl Encode for RequestHeader {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        let version = self.version();
+        self.api_key.encode(buf);
+        self.api_version.encode(buf);
+        self.correlation_id.encode(buf);
+        if version >= 1 {
+            write_nullable_string(buf, self.client_id.as_deref());
+        }
+        if version >= 2 {
+            self.tagged_fields.encode(buf);
+        }
+    }
+}
+
+/// Header in front of every response body
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ResponseHeader {
+    pub correlation_id: i32,
+    /// Present only in header v1
+    pub tagged_fields: TaggedFields,
+}
+
+impl ResponseHeader {
+    /// Decodes a header of the given version from the front of a response frame
+    pub fn decode(cursor: &mut Cursor<'_>, version: i16) -> Result<Self> {
+        let correlation_id = cursor.read()?;
+        let tagged_fields = if version >= 1 {
+            cursor.read()?
+        } else {
+            TaggedFields::new()
+        };
+        Ok(Self {
+            correlation_id,
+            tagged_fields,
+        })
+    }
+}
+
+impl ApiKey {
+    /// First flexible version of the API, or `None` if no version is flexible
+    pub const fn first_flexible_version(self) -> Option<i16> {
+        Some(match self {
+            Self::Produce | Self::Metadata => 9,
+            Self::Fetch => 12,
+            Self::ListOffsets | Self::JoinGroup | Self::OffsetFetch | Self::UpdateMetadata => 6,
+            Self::LeaderAndIsr
+            | Self::Heartbeat
+            | Self::LeaveGroup
+            | Self::SyncGroup
+            | Self::DeleteTopics
+            | Self::OffsetForLeaderEpoch
+            | Self::DescribeConfigs => 4,
+            Self::OffsetCommit => 8,
+            Self::DescribeGroups | Self::CreateTopics => 5,
+            Self::ControlledShutdown
+            | Self::FindCoordinator
+            | Self::ListGroups
+            | Self::ApiVersions
+            | Self::AddPartitionsToTxn
+            | Self::AddOffsetsToTxn
+            | Self::EndTxn
+            | Self::TxnOffsetCommit => 3,
+            Self::StopReplica
+            | Self::DeleteRecords
+            | Self::InitProducerId
+            | Self::DescribeAcls
+            | Self::CreateAcls
+            | Self::DeleteAcls
+            | Self::AlterConfigs
+            | Self::AlterReplicaLogDirs
+            | Self::DescribeLogDirs
+            | Self::SaslAuthenticate
+            | Self::CreatePartitions
+            | Self::CreateDelegationToken
+            | Self::RenewDelegationToken
+            | Self::ExpireDelegationToken
+            | Self::DescribeDelegationToken
+            | Self::DeleteGroups
+            | Self::ElectLeaders => 2,
+            Self::WriteTxnMarkers
+            | Self::IncrementalAlterConfigs
+            | Self::DescribeClientQuotas
+            | Self::AlterClientQuotas
+            | Self::BeginQuorumEpoch
+            | Self::EndQuorumEpoch => 1,
+            Self::SaslHandshake | Self::OffsetDelete => return None,
+            _ => 0,
+        })
+    }
+
+    /// Whether `version` of the API encodes with compact types and tagged fields
+    pub const fn is_flexible(self, version: i16) -> bool {
+        match self.first_flexible_version() {
+            Some(first) => version >= first,
+            None => false,
+        }
+    }
+
+    /// Request header version used for `version` of the API
+    pub const fn request_header_version(self, version: i16) -> i16 {
+        if self.is_flexible(version) {
+            2
+        } else if matches!(self, Self::ControlledShutdown) && version == 0 {
+            // The only request that predates the client id
+            0
+        } else {
+            1
+        }
+    }
+
+    /// Response header version used for `version` of the API
+    pub const fn response_header_version(self, version: i16) -> i16 {
+        if self.is_flexible(version) && !matches!(self, Self::ApiVersions) {
+            1
+        } else {
+            0
+        }
+    }
+}
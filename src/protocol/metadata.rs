@@ -0,0 +1,283 @@
+//! Metadata (key 3): brokers, topics and partition leadership of the cluster.
+
+use super::ApiKey;
+use super::api_versions::VersionRange;
+use super::codec::{
+    Cursor, Encode, TaggedFields, write_array_len, write_compact_nullable_string, write_string,
+};
+use super::uuid::Uuid;
+use crate::error::Result;
+
+/// Metadata versions the client implements
+pub const VERSIONS: VersionRange = VersionRange::new(0, 12);
+
+/// A broker in the cluster
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BrokerMetadata {
+    pub node_id: i32,
+    pub host: String,
+    pub port: i32,
+    /// Rack the broker is in (v1+)
+    pub rack: Option<String>,
+}
+
+/// Leadership and replicas of one partition
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PartitionMetadata {
+    pub error_code: i16,
+    pub partition_index: i32,
+    /// Node id of the leader, -1 if there is none
+    pub leader_id: i32,
+    /// Leader epoch (v7+), -1 if unknown
+    pub leader_epoch: i32,
+    pub replica_nodes: Vec<i32>,
+    /// In-sync replicas
+    pub isr_nodes: Vec<i32>,
+    /// Replicas on offline log directories (v5+)
+    pub offline_replicas: Vec<i32>,
+}
+
+/// One topic and its partitions
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TopicMetadata {
+    pub error_code: i16,
+    /// Topic name; only null in v12+ replies to requests by topic id
+    pub name: Option<String>,
+    /// Topic id (v10+), [`Uuid::ZERO`] if the broker sent none
+    pub topic_id: Uuid,
+    pub is_internal: bool,
+    pub partitions: Vec<PartitionMetadata>,
+    /// Bit field of permitted ACL operations (v8+), `i32::MIN` if not requested
+    pub topic_authorized_operations: i32,
+}
+
+impl TopicMetadata {
+    /// Looks up a partition by index
+    pub fn partition(&self, index: i32) -> Option<&PartitionMetadata> {
+        self.partitions
+            .iter()
+            .find(|partition| partition.partition_index == index)
+    }
+}
+
+/// Decoded Metadata response
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MetadataResponse {
+    pub throttle_time_ms: i32,
+    pub brokers: Vec<BrokerMetadata>,
+    /// Cluster id (v2+)
+    pub cluster_id: Option<String>,
+    /// Node id of the controller (v1+), -1 if unknown
+    pub controller_id: i32,
+    pub topics: Vec<TopicMetadata>,
+}
+
+impl MetadataResponse {
+    /// Looks up a broker by node id
+    pub fn broker(&self, node_id: i32) -> Option<&BrokerMetadata> {
+        self.brokers.iter().find(|broker| broker.node_id == node_id)
+    }
+
+    /// Looks up a topic by name
+    pub fn topic(&self, name: &str) -> Option<&TopicMetadata> {
+        self.topics
+            .iter()
+            .find(|topic| topic.name.as_deref() == Some(name))
+    }
+
+    /// Folds a newer response for some topics into this one
+    ///
+    /// Cluster-wide fields are replaced, topics in `newer` replace those with
+    /// the same name and topics it does not mention are kept.
+    pub fn merge(&mut self, newer: Self) {
+        self.throttle_time_ms = newer.throttle_time_ms;
+        self.brokers = newer.brokers;
+        self.cluster_id = newer.cluster_id;
+        self.controller_id = newer.controller_id;
+        for topic in newer.topics {
+            match self
+                .topics
+                .iter_mut()
+                .find(|known| known.name == topic.name)
+            {
+                Some(known) => *known = topic,
+                None => self.topics.push(topic),
+            }
+        }
+    }
+}
+
+/// Encodes a Metadata request body for the given version
+///
+/// `None` asks for every topic. Topics are never created as a side effect
+/// on versions that let the client choose (v4+).
+pub fn encode_request(version: i16, topics: Option<&[&str]>) -> Vec<u8> {
+    let flexible = ApiKey::Metadata.is_flexible(version);
+    let mut body = Vec::new();
+
+    // v0 has no null array: an empty one means every topic
+    let topics = match topics {
+        None if version == 0 => Some(&[][..]),
+        topics => topics,
+    };
+    write_array_len(&mut body, topics.map(<[&str]>::len), flexible);
+    for &name in topics.unwrap_or_default() {
+        if version >= 10 {
+            Uuid::ZERO.encode(&mut body);
+        }
+        if flexible {
+            write_compact_nullable_string(&mut body, Some(name));
+            TaggedFields::new().encode(&mut body);
+        } else {
+            write_string(&mut body, name);
+        }
+    }
+
+    if version >= 4 {
+        false.encode(&mut body); // allow_auto_topic_creation
+    }
+    if (8..=10).contains(&version) {
+        false.encode(&mut body); // include_cluster_authorized_operations
+    }
+    if version >= 8 {
+        false.encode(&mut body); // include_topic_authorized_operations
+    }
+    if flexible {
+        TaggedFields::new().encode(&mut body);
+    }
+    body
+}
+
+/// Decodes a Metadata response body sent in reply to a request of `version`
+pub fn decode_response(version: i16, body: &[u8]) -> Result<MetadataResponse> {
+    let flexible = ApiKey::Metadata.is_flexible(version);
+    let mut cursor = Cursor::new(body);
+
+    let throttle_time_ms = if version >= 3 { cursor.read()? } else { 0 };
+
+    let mut brokers = Vec::new();
+    for _ in 0..cursor.read_array_len(flexible)?.unwrap_or(0) {
+        let node_id = cursor.read()?;
+        let host = read_string(&mut cursor, flexible)?;
+        let port = cursor.read()?;
+        let rack = if version >= 1 {
+            read_nullable_string(&mut cursor, flexible)?
+        } else {
+            None
+        };
+        skip_tagged_fields(&mut cursor, flexible)?;
+        brokers.push(BrokerMetadata {
+            node_id,
+            host,
+            port,
+            rack,
+        });
+    }
+
+    let cluster_id = if version >= 2 {
+        read_nullable_string(&mut cursor, flexible)?
+    } else {
+        None
+    };
+    let controller_id = if version >= 1 { cursor.read()? } else { -1 };
+
+    let mut topics = Vec::new();
+    for _ in 0..cursor.read_array_len(flexible)?.unwrap_or(0) {
+        topics.push(decode_topic(&mut cursor, version, flexible)?);
+    }
+
+    if (8..=10).contains(&version) {
+        cursor.read::<i32>()?; // cluster_authorized_operations
+    }
+    skip_tagged_fields(&mut cursor, flexible)?;
+    ApiKey::Metadata.check_version(version, topics.iter().map(|topic| topic.error_code))?;
+
+    Ok(MetadataResponse {
+        throttle_time_ms,
+        brokers,
+        cluster_id,
+        controller_id,
+        topics,
+    })
+}
+
+fn decode_topic(cursor: &mut Cursor<'_>, version: i16, flexible: bool) -> Result<TopicMetadata> {
+    let error_code = cursor.read()?;
+    let name = read_nullable_string(cursor, flexible)?;
+    let topic_id = if version >= 10 {
+        cursor.read()?
+    } else {
+        Uuid::ZERO
+    };
+    let is_internal = if version >= 1 { cursor.read()? } else { false };
+
+    let mut partitions = Vec::new();
+    for _ in 0..cursor.read_array_len(flexible)?.unwrap_or(0) {
+        let error_code = cursor.read()?;
+        let partition_index = cursor.read()?;
+        let leader_id = cursor.read()?;
+        let leader_epoch = if version >= 7 { cursor.read()? } else { -1 };
+        let replica_nodes = read_node_ids(cursor, flexible)?;
+        let isr_nodes = read_node_ids(cursor, flexible)?;
+        let offline_replicas = if version >= 5 {
+            read_node_ids(cursor, flexible)?
+        } else {
+            Vec::new()
+        };
+        skip_tagged_fields(cursor, flexible)?;
+        partitions.push(PartitionMetadata {
+            error_code,
+            partition_index,
+            leader_id,
+            leader_epoch,
+            replica_nodes,
+            isr_nodes,
+            offline_replicas,
+        });
+    }
+
+    let topic_authorized_operations = if version >= 8 {
+        cursor.read()?
+    } else {
+        i32::MIN
+    };
+    skip_tagged_fields(cursor, flexible)?;
+
+    Ok(TopicMetadata {
+        error_code,
+        name,
+        topic_id,
+        is_internal,
+        partitions,
+        topic_authorized_operations,
+    })
+}
+
+fn read_node_ids(cursor: &mut Cursor<'_>, flexible: bool) -> Result<Vec<i32>> {
+    (0..cursor.read_array_len(flexible)?.unwrap_or(0))
+        .map(|_| cursor.read())
+        .collect()
+}
+
+fn read_string(cursor: &mut Cursor<'_>, flexible: bool) -> Result<String> {
+    if flexible {
+        cursor.read_compact_string()
+    } else {
+        cursor.read_string()
+    }
+}
+
+fn read_nullable_string(cursor: &mut Cursor<'_>, flexible: bool) -> Result<Option<String>> {
+    if flexible {
+        cursor.read_compact_nullable_string()
+    } else {
+        cursor.read_nullable_string()
+    }
+}
+
+fn skip_tagged_fields(cursor: &mut Cursor<'_>, flexible: bool) -> Result<()> {
+    if flexible {
+        cursor.read::<TaggedFields>()?;
+    }
+    Ok(())
+}
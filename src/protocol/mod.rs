@@ -0,0 +1,333 @@
+//! Wire-level definitions of the Kafka protocol messages the client speaks.
+
+pub mod api_versions;
+pub mod codec;
+pub mod error_code;
+pub mod fetch;
+pub mod find_coordinator;
+pub mod header;
+pub mod metadata;
+pub mod produce;
+pub mod sasl;
+pub mod uuid;
+
+use crate::error::{KafkaError, Result};
+use error_code::ErrorCode;
+
+/// Identifiers of the Kafka protocol APIs
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+#[repr(i16)]
+pub enum ApiKey {
+    Produce = 0,
+    Fetch = 1,
+    ListOffsets = 2,
+    Metadata = 3,
+    LeaderAndIsr = 4,
+    StopReplica = 5,
+    UpdateMetadata = 6,
+    ControlledShutdown = 7,
+    OffsetCommit = 8,
+    OffsetFetch = 9,
+    FindCoordinator = 10,
+    JoinGroup = 11,
+    Heartbeat = 12,
+    LeaveGroup = 13,
+    SyncGroup = 14,
+    DescribeGroups = 15,
+    ListGroups = 16,
+    SaslHandshake = 17,
+    ApiVersions = 18,
+    CreateTopics = 19,
+    DeleteTopics = 20,
+    DeleteRecords = 21,
+    InitProducerId = 22,
+    OffsetForLeaderEpoch = 23,
+    AddPartitionsToTxn = 24,
+    AddOffsetsToTxn = 25,
+    EndTxn = 26,
+    WriteTxnMarkers = 27,
+    TxnOffsetCommit = 28,
+    DescribeAcls = 29,
+    CreateAcls = 30,
+    DeleteAcls = 31,
+    DescribeConfigs = 32,
+    AlterConfigs = 33,
+    AlterReplicaLogDirs = 34,
+    DescribeLogDirs = 35,
+    SaslAuthenticate = 36,
+    CreatePartitions = 37,
+    CreateDelegationToken = 38,
+    RenewDelegationToken = 39,
+    ExpireDelegationToken = 40,
+    DescribeDelegationToken = 41,
+    DeleteGroups = 42,
+    ElectLeaders = 43,
+    IncrementalAlterConfigs = 44,
+    AlterPartitionReassignments = 45,
+    ListPartitionReassignments = 46,
+    OffsetDelete = 47,
+    DescribeClientQuotas = 48,
+    AlterClientQuotas = 49,
+    DescribeUserScramCredentials = 50,
+    AlterUserScramCredentials = 51,
+    Vote = 52,
+    BeginQuorumEpoch = 53,
+    EndQuorumEpoch = 54,
+    DescribeQuorum = 55,
+    AlterPartition = 56,
+    UpdateFeatures = 57,
+    Envelope = 58,
+    FetchSnapshot = 59,
+    DescribeCluster = 60,
+    DescribeProducers = 61,
+    BrokerRegistration = 62,
+    BrokerHeartbeat = 63,
+    UnregisterBroker = 64,
+    DescribeTransactions = 65,
+    ListTransactions = 66,
+    AllocateProducerIds = 67,
+    ConsumerGroupHeartbeat = 68,
+    ConsumerGroupDescribe = 69,
+    ControllerRegistration = 70,
+    GetTelemetrySubscriptions = 71,
+    PushTelemetry = 72,
+    AssignReplicasToDirs = 73,
+    ListClientMetricsResources = 74,
+    DescribeTopicPartitions = 75,
+}
+
+impl ApiKey {
+    /// Every API key, in numeric order
+    pub const ALL: [Self; 76] = [
+        Self::Produce,
+        Self::Fetch,
+        Self::ListOffsets,
+        Self::Metadata,
+        Self::LeaderAndIsr,
+        Self::StopReplica,
+        Self::UpdateMetadata,
+        Self::ControlledShutdown,
+        Self::OffsetCommit,
+        Self::OffsetFetch,
+        Self::FindCoordinator,
+        Self::JoinGroup,
+        Self::Heartbeat,
+        Self::LeaveGroup,
+        Self::SyncGroup,
+        Self::DescribeGroups,
+        Self::ListGroups,
+        Self::SaslHandshake,
+        Self::ApiVersions,
+        Self::CreateTopics,
+        Self::DeleteTopics,
+        Self::DeleteRecords,
+        Self::InitProducerId,
+        Self::OffsetForLeaderEpoch,
+        Self::AddPartitionsToTxn,
+        Self::AddOffsetsToTxn,
+        Self::EndTxn,
+        Self::WriteTxnMarkers,
+        Self::TxnOffsetCommit,
+        Self::DescribeAcls,
+        Self::CreateAcls,
+        Self::DeleteAcls,
+        Self::DescribeConfigs,
+        Self::AlterConfigs,
+        Self::AlterReplicaLogDirs,
+        Self::DescribeLogDirs,
+        Self::SaslAuthenticate,
+        Self::CreatePartitions,
+        Self::CreateDelegationToken,
+        Self::RenewDelegationToken,
+        Self::ExpireDelegationToken,
+        Self::DescribeDelegationToken,
+        Self::DeleteGroups,
+        Self::ElectLeaders,
+        Self::IncrementalAlterConfigs,
+        Self::AlterPartitionReassignments,
+        Self::ListPartitionReassignments,
+        Self::OffsetDelete,
+        Self::DescribeClientQuotas,
+        Self::AlterClientQuotas,
+        Self::DescribeUserScramCredentials,
+        Self::AlterUserScramCredentials,
+        Self::Vote,
+        Self::BeginQuorumEpoch,
+        Self::EndQuorumEpoch,
+        Self::DescribeQuorum,
+        Self::AlterPartition,
+        Self::UpdateFeatures,
+        Self::Envelope,
+        Self::FetchSnapshot,
+        Self::DescribeCluster,
+        Self::DescribeProducers,
+        Self::BrokerRegistration,
+        Self::BrokerHeartbeat,
+        Self::UnregisterBroker,
+        Self::DescribeTransactions,
+        Self::ListTransactions,
+        Self::AllocateProducerIds,
+        Self::ConsumerGroupHeartbeat,
+        Self::ConsumerGroupDescribe,
+        Self::ControllerRegistration,
+        Self::GetTelemetrySubscriptions,
+        Self::PushTelemetry,
+        Self::AssignReplicasToDirs,
+        Self::ListClientMetricsResources,
+        Self::DescribeTopicPartitions,
+    ];
+
+    /// Returns the numeric key sent on the wire
+    pub const fn as_i16(self) -> i16 {
+        self as i16
+    }
+
+    /// Looks up the API with the given numeric key
+    pub fn from_i16(key: i16) -> Option<Self> {
+        usize::try_from(key)
+            .ok()
+            .and_then(|index| Self::ALL.get(index).copied())
+    }
+
+    /// Whether a request may be sent again without risking a duplicate effect
+    ///
+    /// Only APIs that read state qualify; a resent Produce, commit or
+    /// create could be applied twice.
+    pub const fn is_idempotent(self) -> bool {
+        matches!(
+            self,
+            Self::Fetch
+                | Self::ListOffsets
+                | Self::Metadata
+                | Self::OffsetFetch
+                | Self::FindCoordinator
+                | Self::DescribeGroups
+                | Self::ListGroups
+                | Self::ApiVersions
+                | Self::OffsetForLeaderEpoch
+                | Self::DescribeAcls
+                | Self::DescribeConfigs
+                | Self::DescribeLogDirs
+                | Self::DescribeDelegationToken
+                | Self::ListPartitionReassignments
+                | Self::DescribeClientQuotas
+                | Self::DescribeUserScramCredentials
+                | Self::DescribeQuorum
+                | Self::DescribeCluster
+                | Self::DescribeProducers
+                | Self::DescribeTransactions
+                | Self::ListTransactions
+                | Self::ConsumerGroupDescribe
+                | Self::DescribeTopicPartitions
+        )
+    }
+
+    /// Whether `version` responses start with `throttle_time_ms`
+    ///
+    /// Covers every API whose responses lead with the throttle time; others,
+    /// such as Produce, ApiVersions and the delegation token APIs, carry it
+    /// further in or not at all.
+    pub(crate) const fn leading_throttle(self, version: i16) -> bool {
+        // First version leading with throttle_time_ms
+        let since = match self {
+            Self::DeleteRecords
+            | Self::InitProducerId
+            | Self::AddPartitionsToTxn
+            | Self::AddOffsetsToTxn
+            | Self::EndTxn
+            | Self::TxnOffsetCommit
+            | Self::DescribeAcls
+            | Self::CreateAcls
+            | Self::DeleteAcls
+            | Self::DescribeConfigs
+            | Self::AlterConfigs
+            | Self::AlterReplicaLogDirs
+            | Self::DescribeLogDirs
+            | Self::CreatePartitions
+            | Self::DeleteGroups
+            | Self::ElectLeaders
+            | Self::IncrementalAlterConfigs
+            | Self::AlterPartitionReassignments
+            | Self::ListPartitionReassignments
+            | Self::DescribeClientQuotas
+            | Self::AlterClientQuotas
+            | Self::DescribeUserScramCredentials
+            | Self::AlterUserScramCredentials
+            | Self::AlterPartition
+            | Self::UpdateFeatures
+            | Self::FetchSnapshot
+            | Self::DescribeCluster
+            | Self::DescribeProducers
+            | Self::BrokerRegistration
+            | Self::BrokerHeartbeat
+            | Self::UnregisterBroker
+            | Self::DescribeTransactions
+            | Self::ListTransactions
+            | Self::AllocateProducerIds
+            | Self::ConsumerGroupHeartbeat
+            | Self::ConsumerGroupDescribe
+            | Self::ControllerRegistration
+            | Self::GetTelemetrySubscriptions
+            | Self::PushTelemetry
+            | Self::AssignReplicasToDirs
+            | Self::ListClientMetricsResources
+            | Self::DescribeTopicPartitions => 0,
+            Self::Fetch
+            | Self::FindCoordinator
+            | Self::Heartbeat
+            | Self::LeaveGroup
+            | Self::SyncGroup
+            | Self::DescribeGroups
+            | Self::ListGroups
+            | Self::DeleteTopics => 1,
+            Self::ListOffsets
+            | Self::JoinGroup
+            | Self::CreateTopics
+            | Self::OffsetForLeaderEpoch => 2,
+            Self::Metadata | Self::OffsetCommit | Self::OffsetFetch => 3,
+            _ => return false,
+        };
+        version >= since
+    }
+
+    /// Fails with [`KafkaError::UnsupportedVersion`] if any of a response's
+    /// `error_codes` is UNSUPPORTED_VERSION
+    ///
+    /// A broker rejecting a version puts the error in every error field of
+    /// the response, so one such code means the whole request was refused.
+    /// Decoders report it this way so that
+    /// [`send_versioned`](crate::KafkaClient::send_versioned) retries lower.
+    pub(crate) fn check_version(
+        self,
+        version: i16,
+        error_codes: impl IntoIterator<Item = i16>,
+    ) -> Result<()> {
+        let unsupported = ErrorCode::UnsupportedVersion.as_i16();
+        if error_codes.into_iter().any(|code| code == unsupported) {
+            return Err(KafkaError::UnsupportedVersion {
+                api_key: self.as_i16(),
+                version,
+            });
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn leading_throttle_versions() {
+        assert!(ApiKey::DescribeConfigs.leading_throttle(0));
+        assert!(ApiKey::EndTxn.leading_throttle(0));
+        assert!(ApiKey::DeleteGroups.leading_throttle(0));
+        assert!(!ApiKey::OffsetForLeaderEpoch.leading_throttle(1));
+        assert!(ApiKey::OffsetForLeaderEpoch.leading_throttle(2));
+        assert!(!ApiKey::Metadata.leading_throttle(2));
+        assert!(ApiKey::Metadata.leading_throttle(3));
+        assert!(!ApiKey::Produce.leading_throttle(9));
+        assert!(!ApiKey::ApiVersions.leading_throttle(3));
+        assert!(!ApiKey::CreateDelegationToken.leading_throttle(3));
+    }
+}
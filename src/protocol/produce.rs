@@ -0,0 +1,216 @@
+//! Produce (key 0): appending record batches to partition logs.
+
+use std::collections::BTreeMap;
+
+use super::ApiKey;
+use super::api_versions::VersionRange;
+use super::codec::{
+    Cursor, Encode, TaggedFields, write_array_len, write_compact_nullable_bytes,
+    write_compact_nullable_string, write_compact_string, write_nullable_bytes,
+    write_nullable_string, write_string,
+};
+use super::error_code::ErrorCode;
+use crate::error::Result;
+use crate::topic::TopicPartition;
+
+/// Produce versions the client implements; v3 is the first carrying RecordBatch v2
+pub const VERSIONS: VersionRange = VersionRange::new(3, 9);
+
+/// How many replicas must have a batch before the broker answers
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[repr(i16)]
+pub enum Acks {
+    /// The broker does not answer at all
+    None = 0,
+    /// The leader wrote the batch to its log
+    Leader = 1,
+    /// Every in-sync replica has the batch
+    #[default]
+    All = -1,
+}
+
+/// A Produce request: encoded record batches per partition
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ProduceRequest {
+    pub transactional_id: Option<String>,
+    pub acks: Acks,
+    /// How long the broker waits for replication before answering
+    pub timeout_ms: i32,
+    /// Record batches, as built by [`RecordBatchBuilder`](crate::records::RecordBatchBuilder)
+    pub records: BTreeMap<TopicPartition, Vec<u8>>,
+}
+
+/// A batch the broker rejected because of one of its records (v8+)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RecordError {
+    /// Position of the record within its batch
+    pub batch_index: i32,
+    pub message: Option<String>,
+}
+
+/// Outcome of producing to one partition
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PartitionProduceResponse {
+    pub error_code: i16,
+    /// Offset assigned to the first record of the batch
+    pub base_offset: i64,
+    /// Append time if the topic uses LogAppendTime, -1 otherwise
+    pub log_append_time_ms: i64,
+    /// Start offset of the partition log (v5+), -1 if unknown
+    pub log_start_offset: i64,
+    pub record_errors: Vec<RecordError>,
+    pub error_message: Option<String>,
+}
+
+impl PartitionProduceResponse {
+    /// The partition's error, if the batch was not appended
+    pub const fn error(&self) -> Option<ErrorCode> {
+        ErrorCode::from_i16(self.error_code)
+    }
+}
+
+/// Decoded Produce response
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ProduceResponse {
+    pub partitions: BTreeMap<TopicPartition, PartitionProduceResponse>,
+    pub throttle_time_ms: i32,
+}
+
+/// Encodes a Produce request body for the given version
+pub fn encode_request(version: i16, request: &ProduceRequest) -> Vec<u8> {
+    let flexible = ApiKey::Produce.is_flexible(version);
+    let mut body = Vec::with_capacity(
+        64 + request
+            .records
+            .values()
+            .map(|records| records.len() + 32)
+            .sum::<usize>(),
+    );
+
+    if flexible {
+        write_compact_nullable_string(&mut body, request.transactional_id.as_deref());
+    } else {
+        write_nullable_string(&mut body, request.transactional_id.as_deref());
+    }
+    (request.acks as i16).encode(&mut body);
+    request.timeout_ms.encode(&mut body);
+
+    let mut topics: BTreeMap<&str, Vec<(i32, &[u8])>> = BTreeMap::new();
+    for (partition, records) in &request.records {
+        topics
+            .entry(&partition.topic)
+            .or_default()
+            .push((partition.partition, records));
+    }
+
+    write_array_len(&mut body, Some(topics.len()), flexible);
+    for (topic, partitions) in topics {
+        if flexible {
+            write_compact_string(&mut body, topic);
+        } else {
+            write_string(&mut body, topic);
+        }
+        write_array_len(&mut body, Some(partitions.len()), flexible);
+        for (partition, records) in partitions {
+            partition.encode(&mut body);
+            if flexible {
+                write_compact_nullable_bytes(&mut body, Some(records));
+                TaggedFields::new().encode(&mut body);
+            } else {
+                write_nullable_bytes(&mut body, Some(records));
+            }
+        }
+        if flexible {
+            TaggedFields::new().encode(&mut body);
+        }
+    }
+    if flexible {
+        TaggedFields::new().encode(&mut body);
+    }
+    body
+}
+
+/// Decodes a Produce response body sent in reply to a request of `version`
+pub fn decode_response(version: i16, body: &[u8]) -> Result<ProduceResponse> {
+    let flexible = ApiKey::Produce.is_flexible(version);
+    let mut cursor = Cursor::new(body);
+
+    let mut partitions = BTreeMap::new();
+    for _ in 0..cursor.read_array_len(flexible)?.unwrap_or(0) {
+        let topic = if flexible {
+            cursor.read_compact_string()?
+        } else {
+            cursor.read_string()?
+        };
+        for _ in 0..cursor.read_array_len(flexible)?.unwrap_or(0) {
+            let partition = cursor.read()?;
+            let response = decode_partition(&mut cursor, version, flexible)?;
+            partitions.insert(TopicPartition::new(topic.clone(), partition), response);
+        }
+        if flexible {
+            cursor.read::<TaggedFields>()?;
+        }
+    }
+
+    let throttle_time_ms = cursor.read()?;
+    if flexible {
+        cursor.read::<TaggedFields>()?;
+    }
+    ApiKey::Produce.check_version(
+        version,
+        partitions.values().map(|partition| partition.error_code),
+    )?;
+
+    Ok(ProduceResponse {
+        partitions,
+        throttle_time_ms,
+    })
+}
+
+fn decode_partition(
+    cursor: &mut Cursor<'_>,
+    version: i16,
+    flexible: bool,
+) -> Result<PartitionProduceResponse> {
+    let read_nullable_string = |cursor: &mut Cursor<'_>| {
+        if flexible {
+            cursor.read_compact_nullable_string()
+        } else {
+            cursor.read_nullable_string()
+        }
+    };
+
+    let error_code = cursor.read()?;
+    let base_offset = cursor.read()?;
+    let log_append_time_ms = cursor.read()?;
+    let log_start_offset = if version >= 5 { cursor.read()? } else { -1 };
+
+    let mut record_errors = Vec::new();
+    let mut error_message = None;
+    if version >= 8 {
+        for _ in 0..cursor.read_array_len(flexible)?.unwrap_or(0) {
+            let batch_index = cursor.read()?;
+            let message = read_nullable_string(cursor)?;
+            if flexible {
+                cursor.read::<TaggedFields>()?;
+            }
+            record_errors.push(RecordError {
+                batch_index,
+                message,
+            });
+        }
+        error_message = read_nullable_string(cursor)?;
+    }
+    if flexible {
+        cursor.read::<TaggedFields>()?;
+    }
+
+    Ok(PartitionProduceResponse {
+        error_code,
+        base_offset,
+        log_append_time_ms,
+        log_start_offset,
+        record_errors,
+        error_message,
+    })
+}
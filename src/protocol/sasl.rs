@@ -0,0 +1,70 @@
+//! SaslHandshake (key 17) and SaslAuthenticate (key 36): SASL exchanges framed as Kafka requests.
+
+use super::codec::{Cursor, write_bytes, write_string};
+use crate::error::Result;
+
+/// SaslHandshake version whose tokens travel inside SaslAuthenticate requests
+pub const HANDSHAKE_VERSION: i16 = 1;
+
+/// SaslAuthenticate version the client sends
+pub const AUTHENTICATE_VERSION: i16 = 0;
+
+/// Decoded SaslHandshake response
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct HandshakeResponse {
+    pub error_code: i16,
+    /// Mechanisms the broker has enabled
+    pub mechanisms: Vec<String>,
+}
+
+/// Decoded SaslAuthenticate response
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct AuthenticateResponse {
+    pub error_code: i16,
+    pub error_message: Option<String>,
+    /// Server token for mechanisms with more than one round
+    pub auth_bytes: Vec<u8>,
+}
+
+/// Encodes a SaslHandshake request naming the mechanism to use
+pub fn encode_handshake_request(mechanism: &str) -> Vec<u8> {
+    let mut body = Vec::with_capacity(2 + mechanism.len());
+    write_string(&mut body, mechanism);
+    body
+}
+
+/// Decodes a SaslHandshake response body
+pub fn decode_handshake_response(body: &[u8]) -> Result<HandshakeResponse> {
+    let mut cursor = Cursor::new(body);
+    let error_code = cursor.read()?;
+    let count: i32 = cursor.read()?;
+
+    let mut mechanisms = Vec::new();
+    for _ in 0..count.max(0) {
+        mechanisms.push(cursor.read_string()?);
+    }
+    Ok(HandshakeResponse {
+        error_code,
+        mechanisms,
+    })
+}
+
+/// Encodes a SaslAuthenticate request carrying one client token
+pub fn encode_authenticate_request(auth_bytes: &[u8]) -> Vec<u8> {
+    let mut body = Vec::with_capacity(4 + auth_bytes.len());
+    write_bytes(&mut body, auth_bytes);
+    body
+}
+
+/// Decodes a SaslAuthenticate response body
+pub fn decode_authenticate_response(body: &[u8]) -> Result<AuthenticateResponse> {
+    let mut cursor = Cursor::new(body);
+    let error_code = cursor.read()?;
+    let error_message = cursor.read_nullable_string()?;
+    let auth_bytes = cursor.read_bytes()?.to_vec();
+    Ok(AuthenticateResponse {
+        error_code,
+        error_message,
+        auth_bytes,
+    })
+}
@@ -0,0 +1,155 @@
+//! UUID: the 16-byte identifier brokers assign to topics.
+//!
+//! Kafka prints UUIDs as 22 characters of unpadded URL-safe base64, which
+//! is also the form accepted by [`Uuid::from_str`](std::str::FromStr).
+
+use std::fmt;
+use std::str::FromStr;
+
+use super::codec::{Cursor, Decode, Encode};
+use crate::error::{KafkaError, Result};
+
+/// URL-safe base64 alphabet
+const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789-_";
+
+/// A 16-byte identifier, as used for topic ids
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Uuid([u8; 16]);
+
+impl Uuid {
+    /// The all-zero UUID brokers send when no id is known
+    pub const ZERO: Self = Self([0; 16]);
+
+    /// Wraps raw bytes
+    pub const fn from_bytes(bytes: [u8; 16]) -> Self {
+        Self(bytes)
+    }
+
+    /// Raw bytes as sent on the wire
+    pub const fn as_bytes(&self) -> &[u8; 16] {
+        &self.0
+    }
+
+    /// Whether this is [`Uuid::ZERO`]
+    pub fn is_zero(&self) -> bool {
+        *self == Self::ZERO
+    }
+}
+
+impl Encode for Uuid {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        buf.extend_from_slice(&self.0);
+    }
+}
+
+impl Decode for Uuid {
+    fn decode(cursor: &mut Cursor<'_>) -> Result<Self> {
+        cursor.take_array().map(Self)
+    }
+}
+
+impl fmt::Display for Uuid {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let value = u128::from_be_bytes(self.0);
+        // 21 full sextets, then the last 2 bits padded with zeros to a 22nd
+        for index in 0..21 {
+            let sextet = (value >> (122 - 6 * index)) & 0x3f;
+            write!(f, "{}", char::from(ALPHABET[sextet as usize]))?;
+        }
+        let last = (value & 0x3) << 4;
+        write!(f, "{}", char::from(ALPHABET[last as usize]))?;
+        Ok(())
+    }
+}
+
+impl FromStr for Uuid {
+    type Err = KafkaError;
+
+    fn from_str(text: &str) -> Result<Self> {
+        let invalid = || KafkaError::Serialization(format!("`{text}` is not a Kafka UUID"));
+        if text.len() != 22 {
+            return Err(invalid());
+        }
+
+        let mut sextets = text.bytes().map(|byte| {
+            ALPHABET
+                .iter()
+                .position(|&symbol| symbol == byte)
+                .map(|sextet| sextet as u128)
+                .ok_or_else(invalid)
+        });
+        let mut value = 0u128;
+        for sextet in sextets.by_ref().take(21) {
+            value = (value << 6) | sextet?;
+        }
+        // The final character carries the last 2 bits and 4 padding bits, which must be zero
+        let last = sextets.next().ok_or_else(invalid)??;
+        if last & 0xf != 0 {
+            return Err(invalid());
+        }
+        Ok(Self(((value << 2) | (last >> 4)).to_be_bytes()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn known_ids_print_like_kafka() {
+        // Uuid.METADATA_TOPIC_ID in the Java client, i.e. new Uuid(0, 1)
+        let mut bytes = [0; 16];
+        bytes[15] = 1;
+        assert_eq!(
+            Uuid::from_bytes(bytes).to_string(),
+            "AAAAAAAAAAAAAAAAAAAAAQ"
+        );
+        assert_eq!(Uuid::ZERO.to_string(), "AAAAAAAAAAAAAAAAAAAAAA");
+
+        let sequential = Uuid::from_bytes(std::array::from_fn(|index| index as u8));
+        assert_eq!(sequential.to_string(), "AAECAwQFBgcICQoLDA0ODw");
+        assert_eq!(
+            "AAECAwQFBgcICQoLDA0ODw".parse::<Uuid>().unwrap(),
+            sequential
+        );
+    }
+
+    #[test]
+    fn display_and_parse_round_trip() {
+        for bytes in [
+            [0; 16],
+            [0xff; 16],
+            [0x5a; 16],
+            std::array::from_fn(|i| 251 - i as u8),
+        ] {
+            let uuid = Uuid::from_bytes(bytes);
+            let text = uuid.to_string();
+            assert_eq!(text.len(), 22);
+            assert_eq!(text.parse::<Uuid>().unwrap(), uuid);
+        }
+        assert_eq!(
+            "-____________________w".parse::<Uuid>().unwrap().as_bytes()[..2],
+            [0xfb, 0xff]
+        );
+    }
+
+    #[test]
+    fn malformed_ids_are_rejected() {
+        for text in [
+            "",
+            "AAAAAAAAAAAAAAAAAAAAA",
+            "AAAAAAAAAAAAAAAAAAAAAAA",
+            // Standard rather than URL-safe base64
+            "+AAAAAAAAAAAAAAAAAAAAA",
+            "AAAAAAAAAAAAAAAAAAAA/A",
+            // Padding
+            "AAAAAAAAAAAAAAAAAAAA==",
+            // Non-zero padding bits in the last character
+            "AAAAAAAAAAAAAAAAAAAAAB",
+            "00000000-0000-0000-0000-000000000001",
+        ] {
+            let error = text.parse::<Uuid>().unwrap_err();
+            assert!(matches!(error, KafkaError::Serialization(_)), "{text}");
+        }
+    }
+}
@@ -0,0 +1,297 @@
+//! Recording and replaying of broker traffic.
+//!
+//! A recording is a flat file of entries, each one a direction byte
+//! (`>` for bytes sent to the broker, `<` for bytes received), a big-endian
+//! `u32` length and the raw bytes. Recordings let requests be serialized
+//! without a broker (dry-run) and let a captured session be replayed offline
+//! to reproduce protocol bugs.
+
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::Path;
+
+use crate::connection::Connection;
+use crate::error::{KafkaError, Result};
+
+/// Which way a recorded chunk of bytes travelled
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Direction {
+    /// Sent from the client to the broker
+    Request,
+    /// Received by the client from the broker
+    Response,
+}
+
+impl Direction {
+    const fn tag(self) -> u8 {
+        match self {
+            Self::Request => b'>',
+            Self::Response => b'<',
+        }
+    }
+
+    fn from_tag(tag: u8) -> Result<Self> {
+        match tag {
+            b'>' => Ok(Self::Request),
+            b'<' => Ok(Self::Response),
+            other => Err(KafkaError::Serialization(format!(
+                "invalid recording entry tag {other:#04x}"
+            ))),
+        }
+    }
+}
+
+/// Appends one entry to a recording
+pub fn write_entry(sink: &mut impl Write, direction: Direction, bytes: &[u8]) -> io::Result<()> {
+    let len = u32::try_from(bytes.len())
+        .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "recording entry too large"))?;
+    sink.write_all(&[direction.tag()])?;
+    sink.write_all(&len.to_be_bytes())?;
+    sink.write_all(bytes)
+}
+
+/// Reads the next entry from a recording, or `None` at the end of the file
+pub fn read_entry(source: &mut impl Read) -> Result<Option<(Direction, Vec<u8>)>> {
+    let mut tag = [0u8; 1];
+    match source.read_exact(&mut tag) {
+        Ok(()) => {}
+        Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => return Ok(None),
+        Err(e) => return Err(e.into()),
+    }
+    let direction = Direction::from_tag(tag[0])?;
+
+    let mut len = [0u8; 4];
+    source.read_exact(&mut len)?;
+    let mut bytes = vec![0u8; u32::from_be_bytes(len) as usize];
+    source.read_exact(&mut bytes)?;
+    Ok(Some((direction, bytes)))
+}
+
+/// A stream that copies all traffic of an inner stream into a recording
+///
+/// With [`io::Empty`] as the inner stream this is a dry-run transport:
+/// requests are serialized and recorded, but nothing ever answers them.
+pub struct Recorder<S, W: Write> {
+    inner: S,
+    sink: W,
+}
+
+impl<S, W: Write> Recorder<S, W> {
+    /// Records the traffic of `inner` into `sink`
+    pub const fn new(inner: S, sink: W) -> Self {
+        Self { inner, sink }
+    }
+
+    /// Returns the inner stream and the recording sink
+    pub fn into_parts(self) -> (S, W) {
+        (self.inner, self.sink)
+    }
+}
+
+impl<W: Write> Recorder<io::Empty, W> {
+    /// Creates a transport that only records requests and never receives responses
+    pub const fn dry_run(sink: W) -> Self {
+        Self::new(io::empty(), sink)
+    }
+}
+
+impl<S: Read, W: Write> Read for Recorder<S, W> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        if n > 0 {
+            write_entry(&mut self.sink, Direction::Response, &buf[..n])?;
+        }
+        Ok(n)
+    }
+}
+
+impl<S: Write, W: Write> Write for Recorder<S, W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let n = self.inner.write(buf)?;
+        write_entry(&mut self.sink, Direction::Request, &buf[..n])?;
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()?;
+        self.sink.flush()
+    }
+}
+
+/// A stream that serves the responses of a recording instead of a broker
+///
+/// Requests written to it are kept so they can be compared against the
+/// requests of the original session.
+#[derive(Debug, Default)]
+pub struct Replay {
+    responses: io::Cursor<Vec<u8>>,
+    recorded_requests: Vec<u8>,
+    sent: Vec<u8>,
+}
+
+impl Replay {
+    /// Loads every entry of a recording
+    pub fn from_reader(mut source: impl Read) -> Result<Self> {
+        let mut replay = Self::default();
+        while let Some((direction, bytes)) = read_entry(&mut source)? {
+            match direction {
+                Direction::Request => replay.recorded_requests.extend_from_slice(&bytes),
+                Direction::Response => replay.responses.get_mut().extend_from_slice(&bytes),
+            }
+        }
+        Ok(replay)
+    }
+
+    /// Bytes the client sent during the original session
+    pub fn recorded_requests(&self) -> &[u8] {
+        &self.recorded_requests
+    }
+
+    /// Bytes the client sent during the replay
+    pub fn sent(&self) -> &[u8] {
+        &self.sent
+    }
+
+    /// Whether the replayed session sent exactly the requests that were recorded
+    pub fn requests_match(&self) -> bool {
+        self.sent == self.recorded_requests
+    }
+}
+
+impl Read for Replay {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.responses.read(buf)
+    }
+}
+
+impl Write for Replay {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.sent.extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Connection<Recorder<io::Empty, BufWriter<File>>> {
+    /// Creates a connection that writes its requests to a recording file instead of a socket
+    pub fn dry_run(path: impl AsRef<Path>, client_id: &str) -> Result<Self> {
+        let sink = BufWriter::new(File::create(path)?);
+        Ok(Self::new(Recorder::dry_run(sink), client_id))
+    }
+}
+
+impl Connection<Replay> {
+    /// Creates a connection that answers requests from a recording file
+    pub fn replay(path: impl AsRef<Path>, client_id: &str) -> Result<Self> {
+        let replay = Replay::from_reader(BufReader::new(File::open(path)?))?;
+        Ok(Self::new(replay, client_id))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use super::*;
+    use crate::connection::tests::Echo;
+
+    /// Metadata v1, whose responses use header v0
+    const API_KEY: i16 = 3;
+    const API_VERSION: i16 = 1;
+
+    /// A file in the temporary directory, unique to this process and `name`
+    fn temp_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("rust-kafka-{}-{name}", std::process::id()))
+    }
+
+    fn request<S: Read + Write>(connection: &mut Connection<S>, body: &[u8]) -> Result<Vec<u8>> {
+        let id = connection.send_request(API_KEY, API_VERSION, body)?;
+        connection.receive_response(id)
+    }
+
+    #[test]
+    fn entries_round_trip() {
+        let mut recording = Vec::new();
+        write_entry(&mut recording, Direction::Request, b"ping").unwrap();
+        write_entry(&mut recording, Direction::Response, b"").unwrap();
+
+        let mut source = recording.as_slice();
+        assert_eq!(
+            read_entry(&mut source).unwrap(),
+            Some((Direction::Request, b"ping".to_vec()))
+        );
+        assert_eq!(
+            read_entry(&mut source).unwrap(),
+            Some((Direction::Response, Vec::new()))
+        );
+        assert_eq!(read_entry(&mut source).unwrap(), None);
+
+        // Cut inside the bytes of an entry
+        assert!(read_entry(&mut &recording[..7]).is_err());
+    }
+
+    #[test]
+    fn unknown_tag_is_rejected() {
+        let mut recording = Vec::new();
+        write_entry(&mut recording, Direction::Request, b"ping").unwrap();
+        recording[0] = b'?';
+        assert!(matches!(
+            read_entry(&mut recording.as_slice()),
+            Err(KafkaError::Serialization(_))
+        ));
+    }
+
+    #[test]
+    fn recorded_session_replays() {
+        let mut connection = Connection::new(Recorder::new(Echo::default(), Vec::new()), "test");
+        assert_eq!(request(&mut connection, b"first").unwrap(), b"first");
+        assert_eq!(request(&mut connection, b"second").unwrap(), b"second");
+        let (_, recording) = connection.into_inner().into_parts();
+
+        let path = temp_path("replay");
+        std::fs::write(&path, &recording).unwrap();
+        let mut replay = Connection::replay(&path, "test").unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(request(&mut replay, b"first").unwrap(), b"first");
+        assert!(!replay.get_ref().requests_match());
+        assert_eq!(request(&mut replay, b"second").unwrap(), b"second");
+        assert!(replay.get_ref().requests_match());
+    }
+
+    #[test]
+    fn replay_notices_different_requests() {
+        let mut connection = Connection::new(Recorder::new(Echo::default(), Vec::new()), "test");
+        request(&mut connection, b"recorded").unwrap();
+        let (_, recording) = connection.into_inner().into_parts();
+
+        let mut replay =
+            Connection::new(Replay::from_reader(recording.as_slice()).unwrap(), "test");
+        // The response is served regardless; only the comparison tells them apart
+        assert_eq!(request(&mut replay, b"replayed").unwrap(), b"recorded");
+        assert!(!replay.get_ref().requests_match());
+    }
+
+    #[test]
+    fn dry_run_records_requests_only() {
+        let path = temp_path("dry-run");
+        let mut connection = Connection::dry_run(&path, "test").unwrap();
+        let id = connection
+            .send_request(API_KEY, API_VERSION, b"body")
+            .unwrap();
+        assert!(connection.receive_response(id).is_err());
+        let (_, mut sink) = connection.into_inner().into_parts();
+        sink.flush().unwrap();
+        drop(sink);
+
+        let recording = std::fs::read(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        let replay = Replay::from_reader(recording.as_slice()).unwrap();
+        // Size prefix, a 14-byte header with the client id, and the body
+        assert_eq!(&replay.recorded_requests()[..4], &18i32.to_be_bytes());
+        assert_eq!(&replay.recorded_requests()[18..], b"body");
+    }
+}
@@ -0,0 +1,384 @@
+//! Decoding of RecordBatch v2 payloads returned by Fetch.
+
+use super::compression::decompress;
+use super::{CRC_OFFSET, MAGIC, legacy};
+use crate::crc32c::crc32c;
+use crate::error::{KafkaError, Result};
+use crate::protocol::codec::{Cursor, VarInt, VarLong};
+
+/// Bytes before the batch length is known: base offset and length
+const LOG_OVERHEAD: usize = 12;
+
+/// Offset of the magic byte, shared by batches and legacy messages
+const MAGIC_OFFSET: usize = 16;
+
+/// Compression codec of a batch, from attribute bits 0-2
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Compression {
+    None,
+    Gzip,
+    Snappy,
+    Lz4,
+    Zstd,
+}
+
+/// Meaning of record timestamps, from attribute bit 3
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TimestampType {
+    /// Set by the producer
+    CreateTime,
+    /// Set by the broker when appending; every record carries the batch's max timestamp
+    LogAppendTime,
+}
+
+/// A record read from a partition
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Record {
+    pub offset: i64,
+    /// Milliseconds since the epoch, interpreted per the batch's [`TimestampType`]
+    pub timestamp: i64,
+    pub key: Option<Vec<u8>>,
+    pub value: Option<Vec<u8>>,
+    /// Headers in order; a null header value is returned empty
+    pub headers: Vec<(String, Vec<u8>)>,
+}
+
+/// A decoded batch with its header fields
+///
+/// Legacy messages (magic 0 and 1) are presented as batches of one record
+/// without producer fields, as the Java client does.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RecordBatch {
+    pub base_offset: i64,
+    /// Message format version: 2 for batches, 0 or 1 for legacy messages
+    pub magic: i8,
+    pub partition_leader_epoch: i32,
+    pub attributes: i16,
+    /// Codec the records were compressed with, from attribute bits 0-2
+    pub compression: Compression,
+    pub last_offset_delta: i32,
+    pub base_timestamp: i64,
+    pub max_timestamp: i64,
+    pub producer_id: i64,
+    pub producer_epoch: i16,
+    pub base_sequence: i32,
+    /// Records in offset order; empty for compressed batches that could not be decompressed
+    pub records: Vec<Record>,
+}
+
+impl RecordBatch {
+    /// Whether timestamps were set by the producer or the broker
+    pub const fn timestamp_type(&self) -> TimestampType {
+        if self.attributes & 0x08 == 0 {
+            TimestampType::CreateTime
+        } else {
+            TimestampType::LogAppendTime
+        }
+    }
+
+    /// Whether the batch was written inside a transaction
+    pub const fn is_transactional(&self) -> bool {
+        self.attributes & 0x10 != 0
+    }
+
+    /// Whether the batch holds transaction markers rather than application records
+    pub const fn is_control(&self) -> bool {
+        self.attributes & 0x20 != 0
+    }
+
+    /// Whether the records were compressed; such batches hold no records
+    /// unless their codec could be decompressed
+    pub const fn is_compressed(&self) -> bool {
+        !matches!(self.compression, Compression::None)
+    }
+
+    /// Offset of the last record, which may have been removed by compaction
+    ///
+    /// Decoding rejects batches whose last offset overflows; for batches
+    /// built by hand the result saturates.
+    pub const fn last_offset(&self) -> i64 {
+        self.base_offset
+            .saturating_add(self.last_offset_delta as i64)
+    }
+}
+
+/// Decodes every complete batch in a Fetch response's record set
+///
+/// Brokers may cut the last batch short to honour the fetch size; such a
+/// trailing fragment is ignored and fetched again from its offset later.
+/// Old topics may hold legacy messages, which are decoded too. Batches
+/// compressed with a codec that is not compiled in are returned with their
+/// header only, so callers can report them or skip past them.
+pub fn decode_batches(records: &[u8]) -> Result<Vec<RecordBatch>> {
+    Ok(decode_sized_batches(records)?
+        .into_iter()
+        .map(|(batch, _)| batch)
+        .collect())
+}
+
+/// Like [`decode_batches`], keeping each batch's encoded size in bytes
+pub(crate) fn decode_sized_batches(records: &[u8]) -> Result<Vec<(RecordBatch, usize)>> {
+    let mut batches = Vec::new();
+    let mut rest = records;
+
+    while rest.len() >= LOG_OVERHEAD {
+        let length = i32::from_be_bytes(rest[8..LOG_OVERHEAD].try_into().expect("4 bytes"));
+        let Some(end) = usize::try_from(length)
+            .ok()
+            .and_then(|length| length.checked_add(LOG_OVERHEAD))
+        else {
+            return Err(KafkaError::Serialization(format!(
+                "negative batch length {length}"
+            )));
+        };
+        if rest.len() < end {
+            break;
+        }
+
+        let (entry, tail) = rest.split_at(end);
+        let batch = match entry.get(MAGIC_OFFSET).map(|&magic| magic as i8) {
+            Some(MAGIC) => decode_batch(entry)?,
+            Some(0 | 1) => legacy::decode_message(entry)?,
+            Some(magic) => {
+                return Err(KafkaError::Serialization(format!(
+                    "message format v{magic} is not supported"
+                )));
+            }
+            None => {
+                return Err(KafkaError::Serialization(
+                    "record set entry is shorter than its header".into(),
+                ));
+            }
+        };
+        batches.push((batch, end));
+        rest = tail;
+    }
+    Ok(batches)
+}
+
+/// Decodes one complete RecordBatch v2
+fn decode_batch(batch: &[u8]) -> Result<RecordBatch> {
+    let mut cursor = Cursor::new(batch);
+    let base_offset: i64 = cursor.read()?;
+    cursor.read::<i32>()?; // Batch length, already checked
+    let partition_leader_epoch = cursor.read()?;
+    cursor.read::<i8>()?; // Magic
+    let crc: u32 = cursor.read()?;
+    if crc32c(&batch[CRC_OFFSET + 4..]) != crc {
+        return Err(KafkaError::Serialization(format!(
+            "CRC mismatch in batch at offset {base_offset}"
+        )));
+    }
+
+    let attributes = cursor.read()?;
+    let last_offset_delta = cursor.read()?;
+    let base_timestamp = cursor.read()?;
+    let max_timestamp = cursor.read()?;
+    let producer_id = cursor.read()?;
+    let producer_epoch = cursor.read()?;
+    let base_sequence = cursor.read()?;
+    let count: i32 = cursor.read()?;
+    if base_offset
+        .checked_add(i64::from(last_offset_delta))
+        .is_none()
+    {
+        return Err(KafkaError::Serialization(format!(
+            "last offset delta {last_offset_delta} overflows batch at offset {base_offset}"
+        )));
+    }
+
+    let compression = compression(attributes, base_offset)?;
+
+    let inflated;
+    let (mut cursor, count) = if compression == Compression::None {
+        (cursor, count.max(0))
+    } else if let Some(data) = decompress(compression, &batch[cursor.position()..], base_offset)? {
+        inflated = data;
+        (Cursor::new(&inflated), count.max(0))
+    } else {
+        // Records of codecs that are not compiled in stay undecoded
+        (cursor, 0)
+    };
+
+    let log_append_time = attributes & 0x08 != 0;
+    let mut records = Vec::new();
+    for _ in 0..count {
+        let mut record = decode_record(&mut cursor, base_offset, base_timestamp)?;
+        if log_append_time {
+            record.timestamp = max_timestamp;
+        }
+        records.push(record);
+    }
+
+    Ok(RecordBatch {
+        base_offset,
+        magic: MAGIC,
+        partition_leader_epoch,
+        attributes,
+        compression,
+        last_offset_delta,
+        base_timestamp,
+        max_timestamp,
+        producer_id,
+        producer_epoch,
+        base_sequence,
+        records,
+    })
+}
+
+fn decode_record(cursor: &mut Cursor<'_>, base_offset: i64, base_timestamp: i64) -> Result<Record> {
+    let length = read_length(cursor)?.unwrap_or(0);
+    let mut record = Cursor::new(cursor.take(length)?);
+
+    record.read::<i8>()?; // Attributes, unused
+    let timestamp_delta = record.read::<VarLong>()?.0;
+    let offset_delta = record.read::<VarInt>()?.0;
+    let key = read_varint_bytes(&mut record)?;
+    let value = read_varint_bytes(&mut record)?;
+
+    let mut headers = Vec::new();
+    for _ in 0..read_length(&mut record)?.unwrap_or(0) {
+        let name = read_varint_bytes(&mut record)?.unwrap_or_default();
+        let name = String::from_utf8(name)
+            .map_err(|_| KafkaError::Serialization("header key is not valid UTF-8".into()))?;
+        headers.push((name, read_varint_bytes(&mut record)?.unwrap_or_default()));
+    }
+
+    let (Some(offset), Some(timestamp)) = (
+        base_offset.checked_add(i64::from(offset_delta)),
+        base_timestamp.checked_add(timestamp_delta),
+    ) else {
+        return Err(KafkaError::Serialization(format!(
+            "record deltas overflow batch at offset {base_offset}"
+        )));
+    };
+
+    Ok(Record {
+        offset,
+        timestamp,
+        key,
+        value,
+        headers,
+    })
+}
+
+/// Reads a varint length, -1 standing for null
+fn read_length(cursor: &mut Cursor<'_>) -> Result<Option<usize>> {
+    Ok(usize::try_from(cursor.read::<VarInt>()?.0).ok())
+}
+
+fn read_varint_bytes(cursor: &mut Cursor<'_>) -> Result<Option<Vec<u8>>> {
+    read_length(cursor)?
+        .map(|length| cursor.take(length).map(<[u8]>::to_vec))
+        .transpose()
+}
+
+/// Reads the codec from attribute bits 0-2
+pub(super) fn compression(attributes: i16, offset: i64) -> Result<Compression> {
+    match attributes & 0x07 {
+        0 => Ok(Compression::None),
+        1 => Ok(Compression::Gzip),
+        2 => Ok(Compression::Snappy),
+        3 => Ok(Compression::Lz4),
+        4 => Ok(Compression::Zstd),
+        codec => Err(KafkaError::Serialization(format!(
+            "unknown compression codec {codec} at offset {offset}"
+        ))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::records::RecordBatchBuilder;
+
+    /// A batch of two records starting at `base_offset`, with `attributes` patched in
+    fn batch(base_offset: i64, attributes: i16) -> Vec<u8> {
+        let mut builder = RecordBatchBuilder::new();
+        builder.append(1000, Some(b"k"), Some(b"v"), &[]);
+        builder.append(1001, None, Some(b"w"), &[]);
+        let mut batch = builder.build();
+        batch[..8].copy_from_slice(&base_offset.to_be_bytes());
+        batch[CRC_OFFSET + 4..CRC_OFFSET + 6].copy_from_slice(&attributes.to_be_bytes());
+        let crc = crc32c(&batch[CRC_OFFSET + 4..]);
+        batch[CRC_OFFSET..CRC_OFFSET + 4].copy_from_slice(&crc.to_be_bytes());
+        batch
+    }
+
+    #[test]
+    fn compressed_batch_keeps_header_and_later_batches() {
+        let mut set = batch(0, 0);
+        // Snappy is never compiled in
+        set.extend(batch(2, 2));
+        set.extend(batch(4, 0));
+
+        let batches = decode_batches(&set).unwrap();
+        assert_eq!(batches.len(), 3);
+        assert_eq!(batches[0].records.len(), 2);
+        assert_eq!(batches[1].compression, Compression::Snappy);
+        assert!(batches[1].is_compressed());
+        assert!(batches[1].records.is_empty());
+        assert_eq!(batches[1].last_offset(), 3);
+        assert_eq!(batches[2].records[0].offset, 4);
+    }
+
+    #[cfg(feature = "gzip")]
+    #[test]
+    fn gzip_batch_is_decompressed() {
+        use std::io::Write;
+
+        let plain = batch(7, 0);
+        let mut encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::fast());
+        encoder
+            .write_all(&plain[super::super::BATCH_HEADER_LEN..])
+            .unwrap();
+        let mut gzip = plain[..super::super::BATCH_HEADER_LEN].to_vec();
+        gzip.extend(encoder.finish().unwrap());
+        let length = (gzip.len() - LOG_OVERHEAD) as i32;
+        gzip[8..LOG_OVERHEAD].copy_from_slice(&length.to_be_bytes());
+        gzip[CRC_OFFSET + 5] = 1;
+        let crc = crc32c(&gzip[CRC_OFFSET + 4..]);
+        gzip[CRC_OFFSET..CRC_OFFSET + 4].copy_from_slice(&crc.to_be_bytes());
+
+        let decoded = decode_batches(&gzip).unwrap().remove(0);
+        assert_eq!(decoded.compression, Compression::Gzip);
+        assert_eq!(
+            decoded.records,
+            decode_batches(&plain).unwrap().remove(0).records
+        );
+    }
+
+    #[test]
+    fn overflowing_offsets_are_an_error() {
+        assert!(decode_batches(&batch(i64::MAX, 0)).is_err());
+        assert!(decode_batches(&batch(i64::MAX - 1, 0)).is_ok());
+
+        // Record timestamps are deltas from the base timestamp at bytes 27..35
+        let mut set = batch(0, 0);
+        set[27..35].copy_from_slice(&i64::MAX.to_be_bytes());
+        let crc = crc32c(&set[CRC_OFFSET + 4..]);
+        set[CRC_OFFSET..CRC_OFFSET + 4].copy_from_slice(&crc.to_be_bytes());
+        assert!(decode_batches(&set).is_err());
+    }
+
+    #[test]
+    fn unknown_codec_is_an_error() {
+        assert!(decode_batches(&batch(0, 7)).is_err());
+    }
+
+    #[test]
+    fn truncated_tail_is_ignored() {
+        let mut set = batch(0, 0);
+        let whole = set.len();
+        set.extend(&batch(2, 0)[..whole - 1]);
+        assert_eq!(decode_batches(&set).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn corrupt_batch_fails_crc() {
+        let mut set = batch(0, 0);
+        let last = set.len() - 1;
+        set[last] ^= 1;
+        assert!(decode_batches(&set).is_err());
+    }
+}
@@ -0,0 +1,248 @@
+//! Assembly of RecordBatch v2 payloads for produce requests.
+//!
+//! Batches are uncompressed and use create time. Records store their offset
+//! and timestamp as varint deltas from the first record, and the CRC32C
+//! covers everything from the attributes to the end of the batch.
+
+use super::{BATCH_HEADER_LEN, CRC_OFFSET, MAGIC, NO_PRODUCER_ID};
+use crate::crc32c::crc32c;
+use crate::protocol::codec::{Encode, VarInt, VarLong};
+
+/// Builds one uncompressed batch of records for a single partition
+#[derive(Debug, Clone, Default)]
+pub struct RecordBatchBuilder {
+    /// Encoded records, each prefixed with its varint length
+    records: Vec<u8>,
+    count: i32,
+    /// Timestamp of the first record, which the others are stored relative to
+    base_timestamp: Option<i64>,
+    max_timestamp: i64,
+    /// Producer id, epoch and base sequence of an idempotent producer
+    producer: Option<(i64, i16, i32)>,
+    transactional: bool,
+}
+
+impl RecordBatchBuilder {
+    /// Creates an empty batch
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Stamps the batch for an idempotent producer
+    ///
+    /// `base_sequence` is the sequence number of the first record; the broker
+    /// uses it to drop retried duplicates of the batch.
+    pub fn with_producer(
+        mut self,
+        producer_id: i64,
+        producer_epoch: i16,
+        base_sequence: i32,
+    ) -> Self {
+        self.producer = Some((producer_id, producer_epoch, base_sequence));
+        self
+    }
+
+    /// Marks the batch as part of a transaction, which also requires [`with_producer`](Self::with_producer)
+    pub fn with_transactional(mut self) -> Self {
+        self.transactional = true;
+        self
+    }
+
+    /// Appends a record with a create time in milliseconds since the epoch
+    pub fn append(
+        &mut self,
+        timestamp: i64,
+        key: Option<&[u8]>,
+        value: Option<&[u8]>,
+        headers: &[(String, Vec<u8>)],
+    ) -> &mut Self {
+        let base_timestamp = *self.base_timestamp.get_or_insert(timestamp);
+
+        let mut record = Vec::new();
+        0i8.encode(&mut record); // Attributes, unused
+        // Wraps like the Java client; brokers reject such timestamps anyway
+        VarLong(timestamp.wrapping_sub(base_timestamp)).encode(&mut record);
+        VarInt(self.count).encode(&mut record); // Offset delta
+        write_varint_bytes(&mut record, key);
+        write_varint_bytes(&mut record, value);
+        VarInt(headers.len() as i32).encode(&mut record);
+        for (name, value) in headers {
+            write_varint_bytes(&mut record, Some(name.as_bytes()));
+            write_varint_bytes(&mut record, Some(value));
+        }
+
+        VarInt(record.len() as i32).encode(&mut self.records);
+        self.records.extend_from_slice(&record);
+        self.max_timestamp = if self.count == 0 {
+            timestamp
+        } else {
+            self.max_timestamp.max(timestamp)
+        };
+        self.count += 1;
+        self
+    }
+
+    /// Number of records appended so far
+    pub const fn len(&self) -> usize {
+        self.count as usize
+    }
+
+    /// Whether no record was appended
+    pub const fn is_empty(&self) -> bool {
+        self.count == 0
+    }
+
+    /// Encodes the batch; brokers assign the real base offset when appending it
+    pub fn build(&self) -> Vec<u8> {
+        let mut batch = Vec::with_capacity(BATCH_HEADER_LEN + self.records.len());
+        0i64.encode(&mut batch); // Base offset
+        ((BATCH_HEADER_LEN - 12 + self.records.len()) as i32).encode(&mut batch); // Batch length
+        (-1i32).encode(&mut batch); // Partition leader epoch
+        MAGIC.encode(&mut batch);
+        0u32.encode(&mut batch); // CRC, filled in below
+        let attributes: i16 = if self.transactional { 0x10 } else { 0 };
+        attributes.encode(&mut batch); // No compression, create time
+        (self.count - 1).max(0).encode(&mut batch); // Last offset delta
+        self.base_timestamp.unwrap_or(-1).encode(&mut batch);
+        self.base_timestamp
+            .map_or(-1, |_| self.max_timestamp)
+            .encode(&mut batch);
+        let (producer_id, producer_epoch, base_sequence) =
+            self.producer.unwrap_or((NO_PRODUCER_ID, -1, -1));
+        producer_id.encode(&mut batch);
+        producer_epoch.encode(&mut batch);
+        base_sequence.encode(&mut batch);
+        self.count.encode(&mut batch);
+        batch.extend_from_slice(&self.records);
+
+        let crc = crc32c(&batch[CRC_OFFSET + 4..]);
+        batch[CRC_OFFSET..CRC_OFFSET + 4].copy_from_slice(&crc.to_be_bytes());
+        batch
+    }
+}
+
+/// Writes a varint length followed by the bytes, -1 standing for null
+fn write_varint_bytes(buf: &mut Vec<u8>, bytes: Option<&[u8]>) {
+    match bytes {
+        Some(bytes) => {
+            VarInt(bytes.len() as i32).encode(buf);
+            buf.extend_from_slice(bytes);
+        }
+        None => VarInt(-1).encode(buf),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::records::decode_batches;
+
+    // Fixtures encoded by hand from the RecordBatch v2 specification, laid
+    // out as the Java client's MemoryRecordsBuilder writes them. They are not
+    // captures of Java client output. Their CRCs and batch lengths were
+    // checked with the JDK's java.util.zip.CRC32C, which the Java client's
+    // Crc32C uses on Java 9 and later.
+
+    /// Two records, one with a key and header, one with a null key
+    const PLAIN: &[u8] = &[
+        0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x47, 0xff, 0xff, 0xff,
+        0xff, 0x02, 0xf3, 0x4f, 0xc1, 0x7d, 0x00, 0x00, 0x00, 0x00, 0x00, 0x01, 0x00, 0x00, 0x00,
+        0x00, 0x00, 0x00, 0x03, 0xe8, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x03, 0xed, 0xff, 0xff,
+        0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0x00, 0x00, 0x00,
+        0x02, 0x18, 0x00, 0x00, 0x00, 0x02, 0x6b, 0x02, 0x76, 0x02, 0x02, 0x68, 0x02, 0x78, 0x10,
+        0x00, 0x0a, 0x02, 0x01, 0x04, 0x76, 0x76, 0x00,
+    ];
+
+    /// One record from producer 42, epoch 3, base sequence 7
+    const IDEMPOTENT: &[u8] = &[
+        0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x39, 0xff, 0xff, 0xff,
+        0xff, 0x02, 0xa2, 0xc5, 0xce, 0x3e, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+        0x00, 0x00, 0x00, 0x03, 0xe8, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x03, 0xe8, 0x00, 0x00,
+        0x00, 0x00, 0x00, 0x00, 0x00, 0x2a, 0x00, 0x03, 0x00, 0x00, 0x00, 0x07, 0x00, 0x00, 0x00,
+        0x01, 0x0e, 0x00, 0x00, 0x00, 0x01, 0x02, 0x61, 0x00,
+    ];
+
+    /// The idempotent batch with the transactional attribute set
+    const TRANSACTIONAL: &[u8] = &[
+        0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x39, 0xff, 0xff, 0xff,
+        0xff, 0x02, 0x45, 0xf4, 0x60, 0x3a, 0x00, 0x10, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+        0x00, 0x00, 0x00, 0x03, 0xe8, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x03, 0xe8, 0x00, 0x00,
+        0x00, 0x00, 0x00, 0x00, 0x00, 0x2a, 0x00, 0x03, 0x00, 0x00, 0x00, 0x07, 0x00, 0x00, 0x00,
+        0x01, 0x0e, 0x00, 0x00, 0x00, 0x01, 0x02, 0x61, 0x00,
+    ];
+
+    fn plain() -> RecordBatchBuilder {
+        let mut builder = RecordBatchBuilder::new();
+        builder.append(1000, Some(b"k"), Some(b"v"), &[("h".into(), b"x".to_vec())]);
+        builder.append(1005, None, Some(b"vv"), &[]);
+        builder
+    }
+
+    fn single() -> RecordBatchBuilder {
+        let mut builder = RecordBatchBuilder::new().with_producer(42, 3, 7);
+        builder.append(1000, None, Some(b"a"), &[]);
+        builder
+    }
+
+    #[test]
+    fn plain_batch_matches_fixture() {
+        assert_eq!(plain().build(), PLAIN);
+    }
+
+    #[test]
+    fn idempotent_batch_matches_fixture() {
+        assert_eq!(single().build(), IDEMPOTENT);
+    }
+
+    #[test]
+    fn transactional_batch_matches_fixture() {
+        let mut builder = RecordBatchBuilder::new()
+            .with_producer(42, 3, 7)
+            .with_transactional();
+        builder.append(1000, None, Some(b"a"), &[]);
+        assert_eq!(builder.build(), TRANSACTIONAL);
+    }
+
+    #[test]
+    fn build_round_trips_through_decode() {
+        let batches = decode_batches(&plain().build()).unwrap();
+        assert_eq!(batches.len(), 1);
+        let batch = &batches[0];
+        assert_eq!(batch.last_offset_delta, 1);
+        assert_eq!((batch.base_timestamp, batch.max_timestamp), (1000, 1005));
+        assert_eq!(batch.producer_id, NO_PRODUCER_ID);
+        assert!(!batch.is_transactional());
+
+        let records = &batch.records;
+        assert_eq!(records.len(), 2);
+        assert_eq!((records[0].offset, records[0].timestamp), (0, 1000));
+        assert_eq!(records[0].key.as_deref(), Some(&b"k"[..]));
+        assert_eq!(records[0].headers, [("h".to_string(), b"x".to_vec())]);
+        assert_eq!((records[1].offset, records[1].timestamp), (1, 1005));
+        assert_eq!(records[1].key, None);
+        assert_eq!(records[1].value.as_deref(), Some(&b"vv"[..]));
+
+        let batch = &decode_batches(&single().with_transactional().build()).unwrap()[0];
+        assert_eq!(
+            (batch.producer_id, batch.producer_epoch, batch.base_sequence),
+            (42, 3, 7)
+        );
+        assert!(batch.is_transactional());
+    }
+
+    #[test]
+    fn extreme_timestamp_deltas_do_not_panic() {
+        let mut builder = RecordBatchBuilder::new();
+        builder.append(i64::MIN, None, None, &[]);
+        builder.append(i64::MAX, None, None, &[]);
+        // The wrapped delta cannot be decoded back into a valid timestamp
+        assert!(decode_batches(&builder.build()).is_err());
+    }
+
+    #[test]
+    fn empty_batch_has_no_timestamps() {
+        let batch = RecordBatchBuilder::new().build();
+        assert_eq!(batch.len(), BATCH_HEADER_LEN);
+        assert!(decode_batches(&batch).unwrap()[0].records.is_empty());
+    }
+}
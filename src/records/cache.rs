@@ -0,0 +1,242 @@
+//! Bounded cache of decoded batches for tools that re-read the same offsets.
+//!
+//! Debuggers and replay UIs tend to fetch the same few segments again and
+//! again. [`BatchCache`] keeps recently decoded batches keyed by partition
+//! and base offset, evicting the least recently used, so such re-reads skip
+//! both the Fetch round trip and the decoding.
+
+use std::collections::BTreeMap;
+
+use super::RecordBatch;
+use crate::topic::TopicPartition;
+
+/// Identifies a batch by the partition and base offset it was read from
+pub type BatchKey = (TopicPartition, i64);
+
+/// Bounded store of decoded batches, evicting the least recently used
+#[derive(Debug, Clone)]
+pub struct BatchCache {
+    capacity: usize,
+    /// Ordered by key so the batch holding any offset can be found; each
+    /// batch is kept with its encoded size and last use
+    batches: BTreeMap<BatchKey, (RecordBatch, usize, u64)>,
+    /// Tick to key, oldest first
+    order: BTreeMap<u64, BatchKey>,
+    tick: u64,
+    hits: u64,
+    misses: u64,
+}
+
+impl BatchCache {
+    /// Creates a cache holding up to `capacity` batches
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity: capacity.max(1),
+            batches: BTreeMap::new(),
+            order: BTreeMap::new(),
+            tick: 0,
+            hits: 0,
+            misses: 0,
+        }
+    }
+
+    /// Returns the cached batch holding `offset` and its encoded size,
+    /// marking it as recently used
+    pub fn get(
+        &mut self,
+        partition: &TopicPartition,
+        offset: i64,
+    ) -> Option<(&RecordBatch, usize)> {
+        let key = self
+            .batches
+            .range(..=(partition.clone(), offset))
+            .next_back()
+            .filter(|((cached, _), (batch, _, _))| {
+                cached == partition && batch.last_offset() >= offset
+            })
+            .map(|(key, _)| key.clone());
+        let Some(key) = key else {
+            self.misses += 1;
+            return None;
+        };
+
+        self.tick += 1;
+        self.hits += 1;
+        let (batch, size, last_used) = self.batches.get_mut(&key).expect("key was just found");
+        self.order.remove(last_used);
+        *last_used = self.tick;
+        self.order.insert(self.tick, key);
+        Some((batch, *size))
+    }
+
+    /// Returns the batches a fetch of `max_bytes` from `offset` would, if all are cached
+    ///
+    /// Like the broker, the first batch is returned even when larger than
+    /// `max_bytes`. Batches are followed from one offset to the next until
+    /// one would not fit; a gap before that may be the log end or an evicted
+    /// batch, so `None` is returned and the caller has to fetch.
+    pub fn read(
+        &mut self,
+        partition: &TopicPartition,
+        offset: i64,
+        max_bytes: usize,
+    ) -> Option<Vec<RecordBatch>> {
+        let mut batches = Vec::new();
+        let mut total = 0usize;
+        let mut next = offset;
+        loop {
+            let (batch, size) = self.get(partition, next)?;
+            if !batches.is_empty() && total.saturating_add(size) > max_bytes {
+                return Some(batches);
+            }
+            total = total.saturating_add(size);
+            next = batch.last_offset().checked_add(1)?;
+            batches.push(batch.clone());
+        }
+    }
+
+    /// Stores a batch read from `partition` with its encoded size in bytes,
+    /// replacing one with the same base offset
+    pub fn insert(&mut self, partition: &TopicPartition, batch: RecordBatch, size: usize) {
+        self.tick += 1;
+        let key = (partition.clone(), batch.base_offset);
+        if let Some((_, _, last_used)) = self.batches.remove(&key) {
+            self.order.remove(&last_used);
+        } else if self.batches.len() >= self.capacity
+            && let Some((_, oldest)) = self.order.pop_first()
+        {
+            self.batches.remove(&oldest);
+        }
+        self.order.insert(self.tick, key.clone());
+        self.batches.insert(key, (batch, size, self.tick));
+    }
+
+    /// Drops every batch of `partition`, e.g. after its log was truncated or deleted
+    pub fn invalidate(&mut self, partition: &TopicPartition) {
+        let keys: Vec<_> = self
+            .batches
+            .range((partition.clone(), i64::MIN)..=(partition.clone(), i64::MAX))
+            .map(|(key, (_, _, last_used))| (key.clone(), *last_used))
+            .collect();
+        for (key, last_used) in keys {
+            self.batches.remove(&key);
+            self.order.remove(&last_used);
+        }
+    }
+
+    /// Number of batches cached
+    pub fn len(&self) -> usize {
+        self.batches.len()
+    }
+
+    /// Whether no batch is cached
+    pub fn is_empty(&self) -> bool {
+        self.batches.is_empty()
+    }
+
+    /// Number of lookups answered from the cache so far
+    pub const fn hits(&self) -> u64 {
+        self.hits
+    }
+
+    /// Number of lookups that found no batch so far
+    pub const fn misses(&self) -> u64 {
+        self.misses
+    }
+
+    /// Drops every batch
+    pub fn clear(&mut self) {
+        self.batches.clear();
+        self.order.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::records::batch::decode_sized_batches;
+    use crate::records::{RecordBatchBuilder, decode_batches};
+
+    /// A log of two-record batches at offsets 0, 2, 4 and 6, and the size of each
+    fn log() -> (Vec<u8>, usize) {
+        let mut builder = RecordBatchBuilder::new();
+        builder.append(1, None, Some(b"a"), &[]);
+        builder.append(2, None, Some(b"b"), &[]);
+        let batch = builder.build();
+
+        let mut log = Vec::new();
+        for base in [0i64, 2, 4, 6] {
+            let start = log.len();
+            log.extend_from_slice(&batch);
+            log[start..start + 8].copy_from_slice(&base.to_be_bytes());
+        }
+        (log, batch.len())
+    }
+
+    /// What a broker returns for a fetch of `max_bytes` starting in batch `first`
+    fn fetch(log: &[u8], size: usize, first: usize, max_bytes: usize) -> Vec<RecordBatch> {
+        let start = first * size;
+        // Whole batches up to the limit, but always the first one
+        let end = (start + max_bytes.max(size)).min(log.len());
+        decode_batches(&log[start..end]).unwrap()
+    }
+
+    #[test]
+    fn reads_match_fetches() {
+        let partition = TopicPartition::new("t", 0);
+        let (log, size) = log();
+        let mut cache = BatchCache::new(8);
+        for (batch, size) in decode_sized_batches(&log).unwrap() {
+            cache.insert(&partition, batch, size);
+        }
+
+        for (offset, first) in [(0, 0), (1, 0), (3, 1)] {
+            for max_bytes in [0, size - 1, size, 2 * size, 2 * size + 5] {
+                assert_eq!(
+                    cache.read(&partition, offset, max_bytes),
+                    Some(fetch(&log, size, first, max_bytes)),
+                    "offset {offset}, max_bytes {max_bytes}"
+                );
+            }
+        }
+        // Past the last cached batch the log end is unknown
+        assert_eq!(cache.read(&partition, 0, 4 * size), None);
+        assert_eq!(cache.read(&partition, 6, 0), None);
+    }
+
+    #[test]
+    fn gaps_need_a_fetch() {
+        let partition = TopicPartition::new("t", 0);
+        let (log, _) = log();
+        let mut cache = BatchCache::new(8);
+        for (batch, size) in decode_sized_batches(&log).unwrap() {
+            if batch.base_offset != 2 {
+                cache.insert(&partition, batch, size);
+            }
+        }
+
+        // Whether batch 0 fills the fetch depends on the missing batch 2
+        assert_eq!(cache.read(&partition, 0, 0), None);
+        assert_eq!(cache.read(&partition, 4, 0).unwrap().len(), 1);
+        assert!(cache.read(&TopicPartition::new("u", 0), 0, 0).is_none());
+    }
+
+    #[test]
+    fn evicts_least_recently_used() {
+        let partition = TopicPartition::new("t", 0);
+        let (log, _) = log();
+        let mut cache = BatchCache::new(2);
+        let mut batches = decode_sized_batches(&log).unwrap().into_iter();
+        for _ in 0..2 {
+            let (batch, size) = batches.next().unwrap();
+            cache.insert(&partition, batch, size);
+        }
+        assert!(cache.get(&partition, 1).is_some());
+        let (batch, size) = batches.next().unwrap();
+        cache.insert(&partition, batch, size);
+
+        assert!(cache.get(&partition, 2).is_none());
+        assert!(cache.get(&partition, 0).is_some());
+        assert_eq!((cache.len(), cache.hits(), cache.misses()), (2, 2, 1));
+    }
+}
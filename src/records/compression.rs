@@ -0,0 +1,38 @@
+//! Decompression of compressed record sets, for the codecs compiled in.
+//!
+//! Gzip is decoded with the `gzip` feature. Batches and legacy wrappers
+//! using any other codec are returned with their header only, so callers
+//! can report them or skip past them.
+
+use super::batch::Compression;
+use crate::error::Result;
+
+/// Decompresses the records of a batch or the message set of a legacy wrapper
+///
+/// Returns `None` when the codec is not compiled in. `offset` names the
+/// batch in errors about corrupt data.
+#[cfg_attr(not(feature = "gzip"), allow(unused_variables))]
+pub(super) fn decompress(
+    compression: Compression,
+    data: &[u8],
+    offset: i64,
+) -> Result<Option<Vec<u8>>> {
+    match compression {
+        #[cfg(feature = "gzip")]
+        Compression::Gzip => {
+            use std::io::Read;
+
+            // Producers may concatenate gzip members, which Java reads as one stream
+            let mut inflated = Vec::new();
+            flate2::read::MultiGzDecoder::new(data)
+                .read_to_end(&mut inflated)
+                .map_err(|error| {
+                    crate::error::KafkaError::Serialization(format!(
+                        "corrupt gzip data in batch at offset {offset}: {error}"
+                    ))
+                })?;
+            Ok(Some(inflated))
+        }
+        _ => Ok(None),
+    }
+}
@@ -0,0 +1,365 @@
+//! Decoding of legacy messages (magic 0 and 1), still returned by Fetch for
+//! data written before Kafka 0.11.
+//!
+//! Each message is an offset, a size, an IEEE CRC32 over the rest, the magic
+//! byte, attributes, a timestamp (magic 1 only) and a key and value with
+//! 32-bit lengths. A compressed message is a wrapper whose value is a whole
+//! nested message set. The wrapper's offset is that of the last inner
+//! message; with magic 1 the inner offsets are relative, so the first inner
+//! message has offset 0. Wrappers whose codec is not compiled in are
+//! returned without records, like compressed batches.
+
+use super::batch::{Compression, Record, RecordBatch, compression};
+use super::compression::decompress;
+use crate::error::{KafkaError, Result};
+use crate::protocol::codec::Cursor;
+
+/// Offset of the CRC field within a message; the checksum covers everything after it
+const CRC_OFFSET: usize = 12;
+
+/// Bytes before a message's size is known: offset and size
+const LOG_OVERHEAD: usize = 12;
+
+/// Reversed IEEE 802.3 polynomial
+const POLY: u32 = 0xEDB8_8320;
+
+/// Lookup table for processing one byte per step
+static TABLE: [u32; 256] = build_table();
+
+const fn build_table() -> [u32; 256] {
+    let mut table = [0; 256];
+    let mut i = 0;
+    while i < 256 {
+        let mut crc = i as u32;
+        let mut bit = 0;
+        while bit < 8 {
+            crc = if crc & 1 == 0 {
+                crc >> 1
+            } else {
+                (crc >> 1) ^ POLY
+            };
+            bit += 1;
+        }
+        table[i] = crc;
+        i += 1;
+    }
+    table
+}
+
+/// Computes the IEEE CRC32 legacy messages are checksummed with
+fn crc32(data: &[u8]) -> u32 {
+    !data.iter().fold(!0, |crc, &byte| {
+        TABLE[((crc ^ u32::from(byte)) & 0xFF) as usize] ^ (crc >> 8)
+    })
+}
+
+/// One legacy message with its fields parsed
+struct Message {
+    offset: i64,
+    magic: i8,
+    /// Codec bits, plus the timestamp type bit for magic 1
+    attributes: i16,
+    /// -1 for magic 0, which has no timestamps
+    timestamp: i64,
+    key: Option<Vec<u8>>,
+    value: Option<Vec<u8>>,
+}
+
+impl Message {
+    fn into_record(self, offset: i64, timestamp: i64) -> Record {
+        Record {
+            offset,
+            timestamp,
+            key: self.key,
+            value: self.value,
+            headers: Vec::new(),
+        }
+    }
+}
+
+/// The wrapper fields nested messages are resolved against
+#[derive(Debug, Clone, Copy)]
+struct Wrapper {
+    offset: i64,
+    magic: i8,
+    attributes: i16,
+    timestamp: i64,
+}
+
+/// Decodes one complete legacy message, or a wrapper with its nested messages, as a batch
+pub(super) fn decode_message(message: &[u8]) -> Result<RecordBatch> {
+    let message = read_message(message)?;
+    let compression = compression(message.attributes, message.offset)?;
+    let wrapper = Wrapper {
+        offset: message.offset,
+        magic: message.magic,
+        attributes: message.attributes,
+        timestamp: message.timestamp,
+    };
+
+    let mut batch = RecordBatch {
+        base_offset: wrapper.offset,
+        magic: wrapper.magic,
+        partition_leader_epoch: -1,
+        attributes: wrapper.attributes,
+        compression,
+        last_offset_delta: 0,
+        base_timestamp: wrapper.timestamp,
+        max_timestamp: wrapper.timestamp,
+        producer_id: super::NO_PRODUCER_ID,
+        producer_epoch: -1,
+        base_sequence: -1,
+        records: Vec::new(),
+    };
+    if compression == Compression::None {
+        batch
+            .records
+            .push(message.into_record(wrapper.offset, wrapper.timestamp));
+        return Ok(batch);
+    }
+
+    let wrapped = message.value.as_deref().unwrap_or_default();
+    let Some(inner) = decompress(compression, wrapped, wrapper.offset)? else {
+        return Ok(batch);
+    };
+    batch.records = decode_inner(&wrapper, &inner)?;
+    if let (Some(first), Some(last)) = (batch.records.first(), batch.records.last()) {
+        batch.base_offset = first.offset;
+        batch.base_timestamp = first.timestamp;
+        batch.last_offset_delta = last
+            .offset
+            .checked_sub(first.offset)
+            .and_then(|delta| i32::try_from(delta).ok())
+            .ok_or_else(|| {
+                KafkaError::Serialization(format!(
+                    "wrapper at offset {} spans too many offsets",
+                    wrapper.offset
+                ))
+            })?;
+    }
+    Ok(batch)
+}
+
+/// Decodes the nested message set of a wrapper, resolving offsets and timestamps
+fn decode_inner(wrapper: &Wrapper, inner: &[u8]) -> Result<Vec<Record>> {
+    let mut messages = Vec::new();
+    let mut rest = inner;
+    while !rest.is_empty() {
+        let size = rest
+            .get(8..LOG_OVERHEAD)
+            .map(|size| i32::from_be_bytes(size.try_into().expect("4 bytes")))
+            .and_then(|size| usize::try_from(size).ok())
+            .filter(|&size| rest.len() - LOG_OVERHEAD >= size)
+            .ok_or_else(|| {
+                KafkaError::Serialization(format!(
+                    "truncated message inside wrapper at offset {}",
+                    wrapper.offset
+                ))
+            })?;
+        let (entry, tail) = rest.split_at(LOG_OVERHEAD + size);
+        let message = read_message(entry)?;
+        if message.attributes & 0x07 != 0 {
+            return Err(KafkaError::Serialization(format!(
+                "compressed message nested in wrapper at offset {}",
+                wrapper.offset
+            )));
+        }
+        messages.push(message);
+        rest = tail;
+    }
+
+    // Magic 1 stores offsets relative to the first inner message, and the
+    // wrapper carries the absolute offset of the last one
+    let last_relative = messages.last().map_or(0, |message| message.offset);
+    let log_append_time = wrapper.magic >= 1 && wrapper.attributes & 0x08 != 0;
+    messages
+        .into_iter()
+        .map(|message| {
+            let offset = if wrapper.magic >= 1 {
+                wrapper
+                    .offset
+                    .checked_sub(last_relative)
+                    .and_then(|base| base.checked_add(message.offset))
+                    .ok_or_else(|| {
+                        KafkaError::Serialization(format!(
+                            "inner offsets overflow wrapper at offset {}",
+                            wrapper.offset
+                        ))
+                    })?
+            } else {
+                message.offset
+            };
+            let timestamp = if log_append_time {
+                wrapper.timestamp
+            } else {
+                message.timestamp
+            };
+            Ok(message.into_record(offset, timestamp))
+        })
+        .collect()
+}
+
+/// Parses one complete message after checking its CRC
+fn read_message(message: &[u8]) -> Result<Message> {
+    let mut cursor = Cursor::new(message);
+    let offset = cursor.read()?;
+    cursor.read::<i32>()?; // Message size, already checked
+    let crc: u32 = cursor.read()?;
+    if crc32(&message[CRC_OFFSET + 4..]) != crc {
+        return Err(KafkaError::Serialization(format!(
+            "CRC mismatch in message at offset {offset}"
+        )));
+    }
+
+    let magic: i8 = cursor.read()?;
+    let attributes = i16::from(cursor.read::<i8>()?);
+    // Magic 0 has no timestamp; the timestamp type bit is only meaningful from magic 1
+    let (timestamp, attributes) = if magic >= 1 {
+        (cursor.read()?, attributes & 0x0F)
+    } else {
+        (-1, attributes & 0x07)
+    };
+    Ok(Message {
+        offset,
+        magic,
+        attributes,
+        timestamp,
+        key: cursor.read_nullable_bytes()?.map(<[u8]>::to_vec),
+        value: cursor.read_nullable_bytes()?.map(<[u8]>::to_vec),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::protocol::codec::{Encode, write_nullable_bytes};
+
+    /// Encodes one message the way pre-0.11 brokers store it
+    fn message(offset: i64, magic: i8, attributes: i8, timestamp: i64, value: &[u8]) -> Vec<u8> {
+        let mut body = Vec::new();
+        magic.encode(&mut body);
+        attributes.encode(&mut body);
+        if magic >= 1 {
+            timestamp.encode(&mut body);
+        }
+        write_nullable_bytes(&mut body, None);
+        write_nullable_bytes(&mut body, Some(value));
+
+        let mut message = Vec::new();
+        offset.encode(&mut message);
+        ((body.len() + 4) as i32).encode(&mut message);
+        crc32(&body).encode(&mut message);
+        message.extend_from_slice(&body);
+        message
+    }
+
+    #[test]
+    fn crc32_check_value() {
+        assert_eq!(crc32(b"123456789"), 0xCBF4_3926);
+    }
+
+    #[test]
+    fn plain_messages() {
+        let batch = decode_message(&message(5, 0, 0, 0, b"v0")).unwrap();
+        assert_eq!((batch.magic, batch.base_offset), (0, 5));
+        assert_eq!(batch.records[0].timestamp, -1);
+
+        let batch = decode_message(&message(6, 1, 0x08, 1234, b"v1")).unwrap();
+        assert_eq!(
+            batch.timestamp_type(),
+            super::super::batch::TimestampType::LogAppendTime
+        );
+        assert_eq!(batch.records[0].offset, 6);
+        assert_eq!(batch.records[0].timestamp, 1234);
+        assert_eq!(batch.records[0].value.as_deref(), Some(&b"v1"[..]));
+    }
+
+    #[test]
+    fn wrapper_without_codec_keeps_its_header() {
+        let batch = decode_message(&message(12, 1, 0x02, 1000, b"snappy")).unwrap();
+        assert_eq!(batch.compression, Compression::Snappy);
+        assert_eq!(batch.base_offset, 12);
+        assert!(batch.records.is_empty());
+    }
+
+    #[test]
+    fn magic_1_inner_offsets_are_relative_to_the_wrapper() {
+        let mut inner = message(0, 1, 0, 100, b"a");
+        inner.extend(message(1, 1, 0, 101, b"b"));
+        inner.extend(message(2, 1, 0, 102, b"c"));
+
+        let wrapper = Wrapper {
+            offset: 12,
+            magic: 1,
+            attributes: 0x01,
+            timestamp: 500,
+        };
+        let records = decode_inner(&wrapper, &inner).unwrap();
+        let offsets: Vec<_> = records.iter().map(|record| record.offset).collect();
+        assert_eq!(offsets, [10, 11, 12]);
+        assert_eq!(records[1].timestamp, 101);
+
+        // LogAppendTime wrappers override the inner timestamps
+        let wrapper = Wrapper {
+            attributes: 0x09,
+            ..wrapper
+        };
+        let records = decode_inner(&wrapper, &inner).unwrap();
+        assert!(records.iter().all(|record| record.timestamp == 500));
+    }
+
+    #[cfg(feature = "gzip")]
+    #[test]
+    fn gzip_wrapper_yields_inner_records() {
+        use std::io::Write;
+
+        let mut inner = message(0, 1, 0, 100, b"a");
+        inner.extend(message(1, 1, 0, 101, b"b"));
+        let mut encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::fast());
+        encoder.write_all(&inner).unwrap();
+        let wrapped = encoder.finish().unwrap();
+
+        let batch = decode_message(&message(41, 1, 0x01, 101, &wrapped)).unwrap();
+        assert_eq!(batch.compression, Compression::Gzip);
+        assert_eq!((batch.base_offset, batch.last_offset()), (40, 41));
+        assert_eq!(batch.records[0].value.as_deref(), Some(&b"a"[..]));
+        assert_eq!(batch.records[1].timestamp, 101);
+
+        assert!(decode_message(&message(41, 1, 0x01, 101, b"not gzip")).is_err());
+    }
+
+    #[test]
+    fn magic_0_inner_offsets_are_absolute() {
+        let mut inner = message(7, 0, 0, 0, b"a");
+        inner.extend(message(8, 0, 0, 0, b"b"));
+        let wrapper = Wrapper {
+            offset: 8,
+            magic: 0,
+            attributes: 0x01,
+            timestamp: -1,
+        };
+        let records = decode_inner(&wrapper, &inner).unwrap();
+        assert_eq!((records[0].offset, records[1].offset), (7, 8));
+    }
+
+    #[test]
+    fn malformed_inner_sets_are_errors() {
+        let wrapper = Wrapper {
+            offset: 0,
+            magic: 1,
+            attributes: 0x01,
+            timestamp: 0,
+        };
+        let nested = message(0, 1, 0x02, 0, b"x");
+        assert!(decode_inner(&wrapper, &nested).is_err());
+
+        let truncated = message(0, 1, 0, 0, b"x");
+        assert!(decode_inner(&wrapper, &truncated[..truncated.len() - 1]).is_err());
+        assert!(decode_inner(&wrapper, &truncated[..5]).is_err());
+        // A size too small to hold a CRC
+        let mut tiny = 0i64.to_be_bytes().to_vec();
+        tiny.extend(1i32.to_be_bytes());
+        tiny.push(0);
+        assert!(decode_inner(&wrapper, &tiny).is_err());
+    }
+}
@@ -0,0 +1,29 @@
+//! RecordBatch v2, the format messages are produced, stored and fetched in.
+//!
+//! A batch starts with a fixed 61-byte header (base offset, length, leader
+//! epoch, magic, CRC32C, attributes, offset and timestamp ranges, producer
+//! fields and record count) followed by the records, each of which stores
+//! its offset and timestamp as varint deltas from the batch header. Fetch may
+//! also return legacy messages (magic 0 and 1) from older log segments.
+
+pub mod batch;
+pub mod builder;
+pub mod cache;
+mod compression;
+mod legacy;
+
+pub use batch::{Record, RecordBatch, decode_batches};
+pub use builder::RecordBatchBuilder;
+pub use cache::BatchCache;
+
+/// Magic byte identifying RecordBatch v2
+pub const MAGIC: i8 = 2;
+
+/// Bytes in a batch header, up to and including the record count
+pub const BATCH_HEADER_LEN: usize = 61;
+
+/// Offset of the CRC field within the batch header; the checksum covers everything after it
+pub(crate) const CRC_OFFSET: usize = 17;
+
+/// Producer id of batches written without idempotence
+pub const NO_PRODUCER_ID: i64 = -1;
@@ -0,0 +1,341 @@
+//! Fan-out of consumed records to per-partition worker threads.
+//!
+//! Records of one partition are always processed in order by the same
+//! thread, while different partitions are processed in parallel. The pool
+//! tracks which offsets are still being processed so that only offsets whose
+//! predecessors have all completed are ever reported as committable.
+//!
+//! With flow control enabled the pool also reports which partitions to
+//! pause because their queue grew past a high watermark, and which to resume
+//! once it drained below a low watermark, so memory stays bounded without
+//! blocking the poll loop on a single slow partition.
+
+use std::collections::{HashMap, HashSet};
+use std::sync::mpsc::{self, SyncSender};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::thread::{self, JoinHandle};
+
+use crate::offsets::OffsetTracker;
+use crate::topic::TopicPartition;
+
+/// Processing function called for every dispatched record
+type Handler<T> = dyn Fn(&TopicPartition, i64, T) + Send + Sync;
+
+/// Queue depths at which partitions are paused and resumed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FlowControl {
+    /// Pause a partition once this many of its records are in flight
+    pub pause_at: usize,
+    /// Resume a paused partition once its in-flight records dropped to this many
+    pub resume_at: usize,
+}
+
+/// Partitions whose fetching should change state
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct FlowChanges {
+    pub pause: Vec<TopicPartition>,
+    pub resume: Vec<TopicPartition>,
+}
+
+impl FlowChanges {
+    /// Whether no partition changes state
+    pub fn is_empty(&self) -> bool {
+        self.pause.is_empty() && self.resume.is_empty()
+    }
+}
+
+/// A worker thread with its queue of records
+struct Worker<T> {
+    queue: SyncSender<(i64, T)>,
+    thread: JoinHandle<()>,
+}
+
+/// Pool of per-partition workers preserving order within each partition
+pub struct PartitionedWorkerPool<T> {
+    handler: Arc<Handler<T>>,
+    /// Records a worker may have queued before `dispatch` blocks
+    queue_capacity: usize,
+    workers: HashMap<TopicPartition, Worker<T>>,
+    offsets: Arc<Mutex<OffsetTracker>>,
+    flow_control: Option<FlowControl>,
+    /// Partitions reported as paused by `flow_changes`
+    paused: HashSet<TopicPartition>,
+}
+
+impl<T: Send + 'static> PartitionedWorkerPool<T> {
+    /// Creates a pool running `handler` for every record
+    ///
+    /// Each partition queues at most `queue_capacity` records; dispatching
+    /// to a full queue blocks, pushing back on the poll loop.
+    pub fn new(
+        queue_capacity: usize,
+        handler: impl Fn(&TopicPartition, i64, T) + Send + Sync + 'static,
+    ) -> Self {
+        Self {
+            handler: Arc::new(handler),
+            queue_capacity,
+            workers: HashMap::new(),
+            offsets: Arc::new(Mutex::new(OffsetTracker::new())),
+            flow_control: None,
+            paused: HashSet::new(),
+        }
+    }
+
+    /// Enables pausing of partitions whose queues exceed the given depths
+    ///
+    /// `pause_at` should stay below the queue capacity so partitions get
+    /// paused before `dispatch` has to block.
+    pub fn with_flow_control(mut self, flow_control: FlowControl) -> Self {
+        self.flow_control = Some(flow_control);
+        self
+    }
+
+    /// Partitions to pause or resume since the last call, based on current queue depths
+    ///
+    /// Meant to be called once per poll loop iteration, applying the result
+    /// with the consumer's `pause` and `resume`.
+    pub fn flow_changes(&mut self) -> FlowChanges {
+        let mut changes = FlowChanges::default();
+        let Some(flow_control) = self.flow_control else {
+            return changes;
+        };
+
+        for (partition, depth) in lock(&self.offsets).in_flight_counts() {
+            if self.paused.contains(partition) {
+                if depth <= flow_control.resume_at {
+                    self.paused.remove(partition);
+                    changes.resume.push(partition.clone());
+                }
+            } else if depth >= flow_control.pause_at {
+                self.paused.insert(partition.clone());
+                changes.pause.push(partition.clone());
+            }
+        }
+        changes
+    }
+
+    /// Whether `flow_changes` last reported the partition as paused
+    pub fn is_paused(&self, partition: &TopicPartition) -> bool {
+        self.paused.contains(partition)
+    }
+
+    /// Hands a record to the worker of its partition, starting the worker if needed
+    ///
+    /// Gives the record back if the partition's worker has stopped, which
+    /// happens when the handler panicked.
+    pub fn dispatch(
+        &mut self,
+        partition: &TopicPartition,
+        offset: i64,
+        record: T,
+    ) -> Result<(), T> {
+        if !self.workers.contains_key(partition) {
+            let worker = self.spawn(partition.clone());
+            self.workers.insert(partition.clone(), worker);
+        }
+
+        lock(&self.offsets).start(partition, offset);
+
+        let worker = &self.workers[partition];
+        worker.queue.send((offset, record)).map_err(|failed| {
+            lock(&self.offsets).abandon(partition, offset);
+            failed.0.1
+        })
+    }
+
+    /// Offsets that can be committed for every partition seen so far
+    pub fn committable_offsets(&self) -> HashMap<TopicPartition, i64> {
+        lock(&self.offsets).committable_offsets()
+    }
+
+    /// Committable offsets that moved since they were last marked committed
+    ///
+    /// Meant for a periodic commit loop, which calls `mark_committed` for
+    /// every offset the broker accepted.
+    pub fn uncommitted_offsets(&self) -> HashMap<TopicPartition, i64> {
+        lock(&self.offsets).uncommitted_offsets()
+    }
+
+    /// Records that `offset` was committed for a partition
+    pub fn mark_committed(&self, partition: &TopicPartition, offset: i64) {
+        lock(&self.offsets).mark_committed(partition, offset);
+    }
+
+    /// Records of a partition dispatched but not yet completed
+    pub fn in_flight(&self, partition: &TopicPartition) -> usize {
+        lock(&self.offsets).in_flight(partition)
+    }
+
+    /// Stops the worker of a revoked partition after it drained its queue
+    ///
+    /// Returns the offset to commit for the partition before giving it up.
+    pub fn revoke(&mut self, partition: &TopicPartition) -> Option<i64> {
+        self.paused.remove(partition);
+        if let Some(worker) = self.workers.remove(partition) {
+            drop(worker.queue);
+            let _ = worker.thread.join();
+        }
+        lock(&self.offsets).remove(partition)
+    }
+
+    /// Drains every queue, stops all workers and returns the final committable offsets
+    pub fn shutdown(mut self) -> HashMap<TopicPartition, i64> {
+        for (_, worker) in self.workers.drain() {
+            drop(worker.queue);
+            let _ = worker.thread.join();
+        }
+        self.committable_offsets()
+    }
+
+    fn spawn(&self, partition: TopicPartition) -> Worker<T> {
+        let (queue, records) = mpsc::sync_channel::<(i64, T)>(self.queue_capacity);
+        let handler = Arc::clone(&self.handler);
+        let offsets = Arc::clone(&self.offsets);
+
+        let thread = thread::Builder::new()
+            .name(format!("kafka-worker-{partition}"))
+            .spawn(move || {
+                for (offset, record) in records {
+                    handler(&partition, offset, record);
+
+                    lock(&offsets).complete(&partition, offset);
+                }
+            })
+            .expect("failed to spawn partition worker thread");
+
+        Worker { queue, thread }
+    }
+}
+
+/// Locks the offset tracker; a panicking handler never holds the lock, so poisoning is harmless
+fn lock<V>(mutex: &Mutex<V>) -> MutexGuard<'_, V> {
+    mutex
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Condvar;
+    use std::time::{Duration, Instant};
+
+    use super::*;
+
+    /// Blocks handlers until opened
+    #[derive(Default)]
+    struct Gate {
+        open: Mutex<bool>,
+        opened: Condvar,
+    }
+
+    impl Gate {
+        fn wait(&self) {
+            let mut open = lock(&self.open);
+            while !*open {
+                open = self.opened.wait(open).unwrap();
+            }
+        }
+
+        fn open(&self) {
+            *lock(&self.open) = true;
+            self.opened.notify_all();
+        }
+    }
+
+    /// Polls `condition` until it holds, failing after a few seconds
+    fn eventually(mut condition: impl FnMut() -> bool) {
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while !condition() {
+            assert!(Instant::now() < deadline, "condition never held");
+            thread::sleep(Duration::from_millis(1));
+        }
+    }
+
+    #[test]
+    fn partitions_are_processed_in_order() {
+        let seen = Arc::new(Mutex::new(Vec::new()));
+        let recorder = Arc::clone(&seen);
+        let mut pool = PartitionedWorkerPool::new(4, move |partition, offset, value: i64| {
+            assert_eq!(offset, value);
+            lock(&recorder).push((partition.partition, offset));
+        });
+
+        let partitions = [TopicPartition::new("t", 0), TopicPartition::new("t", 1)];
+        for offset in 0..50 {
+            for partition in &partitions {
+                pool.dispatch(partition, offset, offset).unwrap();
+            }
+        }
+        let committable = pool.shutdown();
+        assert_eq!(committable[&partitions[0]], 50);
+        assert_eq!(committable[&partitions[1]], 50);
+
+        let seen = lock(&seen);
+        for partition in [0, 1] {
+            let offsets: Vec<i64> = seen
+                .iter()
+                .filter(|(p, _)| *p == partition)
+                .map(|&(_, offset)| offset)
+                .collect();
+            assert_eq!(offsets, (0..50).collect::<Vec<_>>());
+        }
+    }
+
+    #[test]
+    fn commits_wait_for_unfinished_records() {
+        let gate = Arc::new(Gate::default());
+        let handler_gate = Arc::clone(&gate);
+        let mut pool = PartitionedWorkerPool::new(4, move |_, _, (): ()| handler_gate.wait());
+        let tp = TopicPartition::new("t", 0);
+
+        pool.dispatch(&tp, 7, ()).unwrap();
+        pool.dispatch(&tp, 8, ()).unwrap();
+        assert_eq!(pool.committable_offsets()[&tp], 7);
+        assert_eq!(pool.in_flight(&tp), 2);
+
+        gate.open();
+        eventually(|| pool.in_flight(&tp) == 0);
+        assert_eq!(pool.uncommitted_offsets()[&tp], 9);
+        pool.mark_committed(&tp, 9);
+        assert!(pool.uncommitted_offsets().is_empty());
+        assert_eq!(pool.revoke(&tp), Some(9));
+    }
+
+    #[test]
+    fn deep_queues_pause_until_drained() {
+        let gate = Arc::new(Gate::default());
+        let handler_gate = Arc::clone(&gate);
+        let mut pool = PartitionedWorkerPool::new(8, move |_, _, (): ()| handler_gate.wait())
+            .with_flow_control(FlowControl {
+                pause_at: 3,
+                resume_at: 1,
+            });
+        let tp = TopicPartition::new("t", 0);
+
+        pool.dispatch(&tp, 0, ()).unwrap();
+        pool.dispatch(&tp, 1, ()).unwrap();
+        assert!(pool.flow_changes().is_empty());
+        pool.dispatch(&tp, 2, ()).unwrap();
+        assert_eq!(pool.flow_changes().pause, std::slice::from_ref(&tp));
+        assert!(pool.is_paused(&tp));
+        // Reported once, not on every call
+        assert!(pool.flow_changes().is_empty());
+
+        gate.open();
+        eventually(|| pool.in_flight(&tp) <= 1);
+        assert_eq!(pool.flow_changes().resume, std::slice::from_ref(&tp));
+        assert!(!pool.is_paused(&tp));
+        pool.shutdown();
+    }
+
+    #[test]
+    fn panicked_worker_gives_records_back() {
+        let mut pool = PartitionedWorkerPool::new(1, |_, _, (): ()| panic!("handler failed"));
+        let tp = TopicPartition::new("t", 0);
+        pool.dispatch(&tp, 0, ()).unwrap();
+
+        // Once the worker is gone its queue disconnects
+        eventually(|| pool.dispatch(&tp, 1, ()).is_err());
+        assert_eq!(pool.committable_offsets()[&tp], 0);
+    }
+}